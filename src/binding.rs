@@ -0,0 +1,337 @@
+use anyhow::{anyhow, Error, Result};
+use rdev::{Button, EventType, Key};
+use serde::{de::value::StrDeserializer, de::IntoDeserializer, Deserialize, Serialize};
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+use crate::event::PinAction;
+
+/// 触发键：鼠标按键或键盘按键，名称与rdev一致，如Middle、Left、F9、ControlRight
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Button(Button),
+    Key(Key),
+}
+
+impl Trigger {
+    pub fn press(&self) -> EventType {
+        match *self {
+            Trigger::Button(button) => EventType::ButtonPress(button),
+            Trigger::Key(key) => EventType::KeyPress(key),
+        }
+    }
+
+    pub fn release(&self) -> EventType {
+        match *self {
+            Trigger::Button(button) => EventType::ButtonRelease(button),
+            Trigger::Key(key) => EventType::KeyRelease(key),
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self> {
+        // 单个字母或数字是KeyC、Num1的简写
+        let name = match name.as_bytes() {
+            [c] if c.is_ascii_alphabetic() => format!("Key{}", name.to_ascii_uppercase()),
+            [c] if c.is_ascii_digit() => format!("Num{name}"),
+            _ => name.to_string(),
+        };
+        let name = name.as_str();
+        let de: StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+        if let Ok(button) = Button::deserialize(de) {
+            return Ok(Trigger::Button(button));
+        }
+        let de: StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+        Key::deserialize(de)
+            .map(Trigger::Key)
+            .map_err(|_| anyhow!("未知按键：{name}"))
+    }
+}
+
+impl Trigger {
+    /// 窗口键盘事件对应的按键，窗口内快捷键与全局触发键共用同一套名称
+    pub fn from_virtual_key(key: VirtualKeyCode) -> Option<Self> {
+        use VirtualKeyCode as V;
+        let key = match key {
+            V::Key1 => Key::Num1,
+            V::Key2 => Key::Num2,
+            V::Key3 => Key::Num3,
+            V::Key4 => Key::Num4,
+            V::Key5 => Key::Num5,
+            V::Key6 => Key::Num6,
+            V::Key7 => Key::Num7,
+            V::Key8 => Key::Num8,
+            V::Key9 => Key::Num9,
+            V::Key0 => Key::Num0,
+            V::A => Key::KeyA,
+            V::B => Key::KeyB,
+            V::C => Key::KeyC,
+            V::D => Key::KeyD,
+            V::E => Key::KeyE,
+            V::F => Key::KeyF,
+            V::G => Key::KeyG,
+            V::H => Key::KeyH,
+            V::I => Key::KeyI,
+            V::J => Key::KeyJ,
+            V::K => Key::KeyK,
+            V::L => Key::KeyL,
+            V::M => Key::KeyM,
+            V::N => Key::KeyN,
+            V::O => Key::KeyO,
+            V::P => Key::KeyP,
+            V::Q => Key::KeyQ,
+            V::R => Key::KeyR,
+            V::S => Key::KeyS,
+            V::T => Key::KeyT,
+            V::U => Key::KeyU,
+            V::V => Key::KeyV,
+            V::W => Key::KeyW,
+            V::X => Key::KeyX,
+            V::Y => Key::KeyY,
+            V::Z => Key::KeyZ,
+            V::F1 => Key::F1,
+            V::F2 => Key::F2,
+            V::F3 => Key::F3,
+            V::F4 => Key::F4,
+            V::F5 => Key::F5,
+            V::F6 => Key::F6,
+            V::F7 => Key::F7,
+            V::F8 => Key::F8,
+            V::F9 => Key::F9,
+            V::F10 => Key::F10,
+            V::F11 => Key::F11,
+            V::F12 => Key::F12,
+            V::Escape => Key::Escape,
+            V::Back => Key::Backspace,
+            V::Return => Key::Return,
+            V::Space => Key::Space,
+            V::Tab => Key::Tab,
+            V::Insert => Key::Insert,
+            V::Delete => Key::Delete,
+            V::Home => Key::Home,
+            V::End => Key::End,
+            V::PageUp => Key::PageUp,
+            V::PageDown => Key::PageDown,
+            V::Left => Key::LeftArrow,
+            V::Right => Key::RightArrow,
+            V::Up => Key::UpArrow,
+            V::Down => Key::DownArrow,
+            V::Minus => Key::Minus,
+            V::Equals => Key::Equal,
+            V::Comma => Key::Comma,
+            V::Period => Key::Dot,
+            V::Slash => Key::Slash,
+            V::Numpad0 => Key::Kp0,
+            V::Numpad1 => Key::Kp1,
+            V::Numpad2 => Key::Kp2,
+            V::Numpad3 => Key::Kp3,
+            V::Numpad4 => Key::Kp4,
+            V::Numpad5 => Key::Kp5,
+            V::Numpad6 => Key::Kp6,
+            V::Numpad7 => Key::Kp7,
+            V::Numpad8 => Key::Kp8,
+            V::Numpad9 => Key::Kp9,
+            V::NumpadAdd => Key::KpPlus,
+            V::NumpadSubtract => Key::KpMinus,
+            V::NumpadEnter => Key::KpReturn,
+            _ => return None,
+        };
+        Some(Trigger::Key(key))
+    }
+}
+
+/// 当前按下的修饰键
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl From<ModifiersState> for Modifiers {
+    fn from(state: ModifiersState) -> Self {
+        Self {
+            ctrl: state.ctrl(),
+            alt: state.alt(),
+            shift: state.shift(),
+            meta: state.logo(),
+        }
+    }
+}
+
+impl Modifiers {
+    /// 根据按键事件更新修饰键状态
+    pub fn update(&mut self, trigger: &Trigger, pressed: bool) {
+        match trigger {
+            Trigger::Key(Key::ControlLeft | Key::ControlRight) => self.ctrl = pressed,
+            Trigger::Key(Key::Alt | Key::AltGr) => self.alt = pressed,
+            Trigger::Key(Key::ShiftLeft | Key::ShiftRight) => self.shift = pressed,
+            Trigger::Key(Key::MetaLeft | Key::MetaRight) => self.meta = pressed,
+            _ => {}
+        }
+    }
+
+    /// 触发键本身是修饰键时，不要求它出现在组合里
+    fn without(mut self, trigger: &Trigger) -> Self {
+        self.update(trigger, false);
+        self
+    }
+}
+
+/// 组合键，如"Ctrl+Middle"、"Alt+Left"、"F9"
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    pub trigger: Trigger,
+}
+
+impl Chord {
+    pub fn matches(&self, trigger: &Trigger, modifiers: &Modifiers) -> bool {
+        self.trigger == *trigger && self.modifiers == modifiers.without(trigger)
+    }
+}
+
+impl From<Trigger> for Chord {
+    fn from(trigger: Trigger) -> Self {
+        Self {
+            modifiers: Modifiers::default(),
+            trigger,
+        }
+    }
+}
+
+impl From<Chord> for String {
+    fn from(chord: Chord) -> Self {
+        let mut parts = Vec::new();
+        let modifiers = chord.modifiers;
+        for (pressed, name) in [
+            (modifiers.ctrl, "Ctrl"),
+            (modifiers.alt, "Alt"),
+            (modifiers.shift, "Shift"),
+            (modifiers.meta, "Meta"),
+        ] {
+            if pressed {
+                parts.push(name.to_string());
+            }
+        }
+        parts.push(match chord.trigger {
+            Trigger::Button(button) => format!("{button:?}"),
+            Trigger::Key(key) => format!("{key:?}"),
+        });
+        parts.join("+")
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let mut parts: Vec<&str> = value.split('+').map(str::trim).collect();
+        let trigger = parts
+            .pop()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("组合键为空"))?;
+        let trigger = Trigger::parse(trigger)?;
+
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                "meta" | "win" | "super" => modifiers.meta = true,
+                _ => return Err(anyhow!("未知修饰键：{part}")),
+            }
+        }
+
+        Ok(Self { modifiers, trigger })
+    }
+}
+
+/// 截图触发绑定：未设置end时按住start拖动、松开结束；设置end时按start开始、按end结束
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub start: Chord,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Chord>,
+    /// 截图后立即按该语言配置识别
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<String>,
+}
+
+impl Default for Binding {
+    fn default() -> Self {
+        Self {
+            start: Chord::from(Trigger::Button(Button::Middle)),
+            end: None,
+            ocr: None,
+        }
+    }
+}
+
+/// 贴图窗口快捷键，每个操作可绑定多个组合键
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keymap {
+    pub close: Vec<Chord>,
+    pub copy: Vec<Chord>,
+    pub save: Vec<Chord>,
+    pub save_as: Vec<Chord>,
+    pub ocr: Vec<Chord>,
+    pub undo: Vec<Chord>,
+    pub redo: Vec<Chord>,
+    pub zoom_in: Vec<Chord>,
+    pub zoom_out: Vec<Chord>,
+    pub zoom_reset: Vec<Chord>,
+    pub click_through: Vec<Chord>,
+    pub close_all: Vec<Chord>,
+}
+
+impl Keymap {
+    /// 按键对应的贴图操作
+    pub fn action(&self, trigger: &Trigger, modifiers: &Modifiers) -> Option<PinAction> {
+        [
+            (&self.close, PinAction::Close),
+            (&self.copy, PinAction::Copy),
+            (&self.save, PinAction::Save),
+            (&self.save_as, PinAction::SaveAs),
+            (&self.ocr, PinAction::Ocr),
+            (&self.undo, PinAction::Undo),
+            (&self.redo, PinAction::Redo),
+            (&self.zoom_in, PinAction::ZoomIn),
+            (&self.zoom_out, PinAction::ZoomOut),
+            (&self.zoom_reset, PinAction::Zoom(1.0)),
+            (&self.click_through, PinAction::ClickThrough),
+            (&self.close_all, PinAction::CloseAll),
+        ]
+        .into_iter()
+        .find(|(chords, _)| chords.iter().any(|c| c.matches(trigger, modifiers)))
+        .map(|(_, action)| action)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let chords = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| Chord::try_from(name.to_string()).ok())
+                .collect()
+        };
+        Self {
+            close: chords(&["Escape"]),
+            copy: chords(&["Ctrl+C"]),
+            save: chords(&["Ctrl+S"]),
+            save_as: chords(&["Ctrl+Shift+S"]),
+            ocr: chords(&["Ctrl+O"]),
+            undo: chords(&["Ctrl+Z"]),
+            redo: chords(&["Ctrl+Y", "Ctrl+Shift+Z"]),
+            // 主键盘上的+需要按住Shift
+            zoom_in: chords(&["Equal", "Shift+Equal", "KpPlus"]),
+            zoom_out: chords(&["Minus", "KpMinus"]),
+            zoom_reset: chords(&["0", "Kp0"]),
+            click_through: chords(&["T"]),
+            close_all: chords(&["Ctrl+Shift+W"]),
+        }
+    }
+}
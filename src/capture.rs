@@ -1,20 +1,94 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use image::{imageops, ImageBuffer, RgbaImage};
 use screenshots::{Image, Screen};
 
 use crate::lens::Lens;
 
 /// TODO fork and change
 pub trait ScreenExt {
+    fn bounds(&self) -> Lens;
     fn capture_lens(&self, lens: &Lens) -> Result<Image>;
 }
 
 impl ScreenExt for Screen {
+    /// 屏幕在虚拟桌面中的物理像素范围
+    fn bounds(&self) -> Lens {
+        let scale_factor = self.display_info.scale_factor;
+        Lens {
+            x: (self.display_info.x as f32 * scale_factor).round() as i32,
+            y: (self.display_info.y as f32 * scale_factor).round() as i32,
+            width: (self.display_info.width as f32 * scale_factor).round() as u32,
+            height: (self.display_info.height as f32 * scale_factor).round() as u32,
+        }
+    }
+
+    /// 截取区域，lens为虚拟桌面坐标，转换为相对本屏幕的逻辑坐标
     fn capture_lens(&self, lens: &Lens) -> Result<Image> {
-        let x = lens.x as f32 / self.display_info.scale_factor;
-        let y = lens.y as f32 / self.display_info.scale_factor;
-        let w = lens.width as f32 / self.display_info.scale_factor;
-        let h = lens.height as f32 / self.display_info.scale_factor;
+        let bounds = self.bounds();
+        let scale_factor = self.display_info.scale_factor;
+        let x = (lens.x - bounds.x) as f32 / scale_factor;
+        let y = (lens.y - bounds.y) as f32 / scale_factor;
+        let w = lens.width as f32 / scale_factor;
+        let h = lens.height as f32 / scale_factor;
 
         self.capture_area(x as i32, y as i32, w as u32, h as u32)
     }
 }
+
+/// 截取跨越多个屏幕的区域：逐屏截取相交部分，统一缩放到物理像素后拼接
+pub fn capture(lens: &Lens) -> Result<Image> {
+    let parts: Vec<(Screen, Lens)> = Screen::all()?
+        .into_iter()
+        .filter_map(|screen| {
+            let part = screen.bounds().intersect(lens)?;
+            Some((screen, part))
+        })
+        .collect();
+
+    match parts.as_slice() {
+        [] => Err(anyhow!("截图区域不在任何屏幕内")),
+        // 单屏完整覆盖时无需拼接
+        [(screen, part)] if part == lens => screen.capture_lens(lens),
+        _ => {
+            let mut canvas: RgbaImage = ImageBuffer::new(lens.width, lens.height);
+            for (screen, part) in parts.iter() {
+                let image = screen.capture_lens(part)?;
+                let image: RgbaImage =
+                    ImageBuffer::from_raw(image.width(), image.height(), image.rgba().to_vec())
+                        .ok_or_else(|| anyhow!("截图数据长度不符"))?;
+                // 各屏缩放比例不同，截图尺寸可能与物理像素不一致
+                let image = if image.dimensions() == (part.width, part.height) {
+                    image
+                } else {
+                    imageops::resize(
+                        &image,
+                        part.width,
+                        part.height,
+                        imageops::FilterType::Triangle,
+                    )
+                };
+                imageops::replace(
+                    &mut canvas,
+                    &image,
+                    (part.x - lens.x) as i64,
+                    (part.y - lens.y) as i64,
+                );
+            }
+            Ok(Image::new(lens.width, lens.height, canvas.into_raw()))
+        }
+    }
+}
+
+/// 与区域重叠面积最大的屏幕序号
+pub fn monitor(lens: &Lens) -> Option<usize> {
+    Screen::all()
+        .ok()?
+        .iter()
+        .enumerate()
+        .filter_map(|(index, screen)| {
+            let part = screen.bounds().intersect(lens)?;
+            Some((index, part.width as u64 * part.height as u64))
+        })
+        .max_by_key(|(_, area)| *area)
+        .map(|(index, _)| index)
+}
@@ -0,0 +1,353 @@
+use std::{
+    cmp::Reverse,
+    env, fs,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lazy_static::lazy_static;
+use log_error::LogError;
+use screenshots::Image;
+
+use crate::image::ImageExt;
+
+const TEMP_DIR: &str = "middle-screenshot";
+/// 临时目录最多保留的截图数
+const TEMP_KEEP: usize = 10;
+
+lazy_static! {
+    static ref CLIPBOARD: Mutex<Box<dyn Clipboard + Send>> = Mutex::new(system());
+}
+
+/// 剪切板后端
+pub trait Clipboard {
+    fn set_image(&mut self, image: &Image) -> Result<()>;
+    fn set_text(&mut self, text: &str) -> Result<()>;
+}
+
+/// 同时写入剪切板的多种图片格式，由粘贴方挑选支持的最佳格式
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct Formats {
+    pub png: Vec<u8>,
+    /// 完整bmp文件，写入CF_DIB时需去掉14字节文件头
+    pub bmp: Vec<u8>,
+    /// 以data URI内嵌png的<img>片段
+    pub html: String,
+    /// 保存到临时目录的png文件，保存失败时为None
+    pub file: Option<PathBuf>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl Formats {
+    pub fn new(image: &Image) -> Result<Self> {
+        let png = image.to_png()?;
+        let bmp = image.to_bmp()?;
+        let html = format!(
+            r#"<img src="data:image/png;base64,{}" width="{}" height="{}">"#,
+            STANDARD.encode(&png),
+            image.width(),
+            image.height()
+        );
+        let file = save_temp(&png).log_error("保存临时截图失败");
+        Ok(Self {
+            png,
+            bmp,
+            html,
+            file,
+        })
+    }
+}
+
+/// 保存png到临时目录，只保留最近的几张
+#[cfg_attr(not(windows), allow(dead_code))]
+fn save_temp(png: &[u8]) -> Result<PathBuf> {
+    let dir = env::temp_dir().join(TEMP_DIR);
+    fs::create_dir_all(&dir)?;
+
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(&dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.extension()? != "png" {
+                return None;
+            }
+            Some((entry.metadata().ok()?.modified().ok()?, path))
+        })
+        .collect();
+    files.sort_by_key(|(modified, _)| Reverse(*modified));
+    for (_, path) in files.into_iter().skip(TEMP_KEEP - 1) {
+        fs::remove_file(path).log_error("删除临时截图失败");
+    }
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = dir.join(format!("screenshot-{millis}.png"));
+    fs::write(&path, png)?;
+    Ok(path)
+}
+
+#[cfg(windows)]
+pub use self::windows::WindowsClipboard;
+
+#[cfg(windows)]
+mod windows {
+    use anyhow::{anyhow, Result};
+    use clipboard_win::{formats, raw, set_clipboard, Clipboard as SystemClipboard};
+    use screenshots::Image;
+
+    use super::{Clipboard, Formats};
+
+    /// bmp文件头长度，CF_DIB从信息头开始
+    const BMP_FILE_HEADER: usize = 14;
+
+    pub struct WindowsClipboard;
+
+    impl Clipboard for WindowsClipboard {
+        /// 一次打开剪切板写入文件、DIB、PNG、HTML四种格式
+        fn set_image(&mut self, image: &Image) -> Result<()> {
+            let formats = Formats::new(image)?;
+            let _clipboard = SystemClipboard::new_attempts(10).map_err(|e| anyhow!(e))?;
+
+            // set_file_list会先清空剪切板，其余格式追加写入
+            match formats.file.as_ref().and_then(|file| file.to_str()) {
+                Some(file) => raw::set_file_list(&[file]).map_err(|e| anyhow!(e))?,
+                None => raw::empty().map_err(|e| anyhow!(e))?,
+            }
+            raw::set_without_clear(formats::CF_DIB, &formats.bmp[BMP_FILE_HEADER..])
+                .map_err(|e| anyhow!(e))?;
+            if let Some(png) = raw::register_format("PNG") {
+                raw::set_without_clear(png.get(), &formats.png).map_err(|e| anyhow!(e))?;
+            }
+            if let Some(html) = raw::register_format("HTML Format") {
+                raw::set_without_clear(html.get(), cf_html(&formats.html).as_bytes())
+                    .map_err(|e| anyhow!(e))?;
+            }
+            Ok(())
+        }
+
+        fn set_text(&mut self, text: &str) -> Result<()> {
+            set_clipboard(formats::Unicode, text).map_err(|e| anyhow!(e))
+        }
+    }
+
+    /// 按CF_HTML格式加上描述头，偏移量为UTF-8字节数
+    fn cf_html(fragment: &str) -> String {
+        const HEADER_LEN: usize = 105;
+        let prefix = "<html><body><!--StartFragment-->";
+        let suffix = "<!--EndFragment--></body></html>";
+        let start_fragment = HEADER_LEN + prefix.len();
+        let end_fragment = start_fragment + fragment.len();
+        let end_html = end_fragment + suffix.len();
+        let header = format!(
+            "Version:0.9\r\nStartHTML:{HEADER_LEN:010}\r\nEndHTML:{end_html:010}\r\nStartFragment:{start_fragment:010}\r\nEndFragment:{end_fragment:010}\r\n"
+        );
+        debug_assert_eq!(header.len(), HEADER_LEN);
+        format!("{header}{prefix}{fragment}{suffix}")
+    }
+}
+
+#[cfg(not(windows))]
+pub use self::unix::UnixClipboard;
+
+#[cfg(not(windows))]
+mod unix {
+    use std::borrow::Cow;
+
+    use anyhow::Result;
+    use arboard::ImageData;
+    use screenshots::Image;
+
+    use super::Clipboard;
+
+    /// X11/Wayland剪切板，X11下需要保持实例存活才能继续提供剪切板内容
+    /// arboard每次写入只保留一种格式，图片以png提供
+    pub struct UnixClipboard(arboard::Clipboard);
+
+    impl UnixClipboard {
+        pub fn new() -> Result<Self> {
+            Ok(Self(arboard::Clipboard::new()?))
+        }
+    }
+
+    impl Clipboard for UnixClipboard {
+        fn set_image(&mut self, image: &Image) -> Result<()> {
+            self.0.set_image(ImageData {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                bytes: Cow::Borrowed(image.rgba()),
+            })?;
+            Ok(())
+        }
+
+        fn set_text(&mut self, text: &str) -> Result<()> {
+            self.0.set_text(text)?;
+            Ok(())
+        }
+    }
+}
+
+/// 内存剪切板，系统剪切板不可用时兜底，也用于无界面测试
+/// 克隆出的实例共享内容，安装后仍可通过原实例读取
+#[cfg(any(test, not(windows)))]
+#[derive(Default, Clone)]
+pub struct MemoryClipboard(std::sync::Arc<Mutex<Contents>>);
+
+#[cfg(any(test, not(windows)))]
+#[derive(Default)]
+struct Contents {
+    image: Option<Image>,
+    text: Option<String>,
+}
+
+#[cfg(any(test, not(windows)))]
+impl MemoryClipboard {
+    fn contents(&self) -> MutexGuard<'_, Contents> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    #[cfg(test)]
+    pub fn image(&self) -> Option<Image> {
+        self.contents()
+            .image
+            .as_ref()
+            .map(|image| Image::new(image.width(), image.height(), image.rgba().to_vec()))
+    }
+
+    #[cfg(test)]
+    pub fn text(&self) -> Option<String> {
+        self.contents().text.clone()
+    }
+}
+
+#[cfg(any(test, not(windows)))]
+impl Clipboard for MemoryClipboard {
+    fn set_image(&mut self, image: &Image) -> Result<()> {
+        let mut contents = self.contents();
+        contents.image = Some(Image::new(
+            image.width(),
+            image.height(),
+            image.rgba().to_vec(),
+        ));
+        contents.text = None;
+        Ok(())
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        let mut contents = self.contents();
+        contents.text = Some(text.to_string());
+        contents.image = None;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+fn system() -> Box<dyn Clipboard + Send> {
+    Box::new(WindowsClipboard)
+}
+
+#[cfg(not(windows))]
+fn system() -> Box<dyn Clipboard + Send> {
+    match UnixClipboard::new() {
+        Ok(clipboard) => Box::new(clipboard),
+        Err(e) => {
+            tracing::error!("系统剪切板不可用，改用内存剪切板：{e:?}");
+            Box::new(MemoryClipboard::default())
+        }
+    }
+}
+
+fn lock() -> MutexGuard<'static, Box<dyn Clipboard + Send>> {
+    CLIPBOARD
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// 替换剪切板后端，返回原后端
+#[cfg(test)]
+pub fn install(clipboard: Box<dyn Clipboard + Send>) -> Box<dyn Clipboard + Send> {
+    std::mem::replace(&mut *lock(), clipboard)
+}
+
+pub fn set_image(image: &Image) -> Result<()> {
+    lock().set_image(image)
+}
+
+pub fn set_text(text: &str) -> Result<()> {
+    lock().set_text(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use lazy_static::lazy_static;
+
+    use super::*;
+    use crate::{
+        lens::Lens,
+        ocr::Recognition,
+        output::{self, OcrFormat},
+    };
+
+    lazy_static! {
+        /// 剪切板是全局的，测试之间串行
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    fn memory() -> (MutexGuard<'static, ()>, MemoryClipboard) {
+        let guard = SERIAL
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let clipboard = MemoryClipboard::default();
+        install(Box::new(clipboard.clone()));
+        (guard, clipboard)
+    }
+
+    #[test]
+    fn image_replaces_text() {
+        let (_guard, clipboard) = memory();
+        set_text("旧文字").unwrap();
+        set_image(&Image::new(2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8])).unwrap();
+
+        let image = clipboard.image().unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.rgba(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(clipboard.text(), None);
+    }
+
+    #[test]
+    fn text_replaces_image() {
+        let (_guard, clipboard) = memory();
+        set_image(&Image::new(1, 1, vec![0; 4])).unwrap();
+        set_text("中文 text").unwrap();
+
+        assert_eq!(clipboard.text().as_deref(), Some("中文 text"));
+        assert!(clipboard.image().is_none());
+    }
+
+    #[test]
+    fn deliver_to_clipboard() {
+        let (_guard, clipboard) = memory();
+        let recognition = Recognition {
+            text: "识别结果\n".to_string(),
+            words: Vec::new(),
+            document: None,
+        };
+        let lens = Lens {
+            x: -10,
+            y: 20,
+            width: 30,
+            height: 40,
+        };
+
+        output::deliver(&recognition, OcrFormat::Text, &lens, None).unwrap();
+        assert_eq!(clipboard.text().as_deref(), Some("识别结果\n"));
+
+        output::deliver(&recognition, OcrFormat::Json, &lens, None).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&clipboard.text().unwrap()).unwrap();
+        assert_eq!(json["text"], "识别结果\n");
+        assert_eq!(json["bbox"], serde_json::json!([-10, 20, 30, 40]));
+    }
+}
@@ -0,0 +1,559 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use lazy_static::lazy_static;
+use log_error::LogError;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    binding::{Binding, Keymap},
+    event::Event,
+    output::{OcrFormat, OcrOutput},
+    preprocess::Step,
+    save::{self, SaveFormat},
+};
+
+const CONFIG_DIR: &str = "middle-screenshot";
+const CONFIG_FILE: &str = "config.toml";
+
+lazy_static! {
+    static ref CURRENT: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+/// 用户配置，位于用户配置目录下的middle-screenshot/config.toml
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 日志文件，重启后生效
+    pub log_file: String,
+    pub capture: CaptureConfig,
+    pub gesture: GestureConfig,
+    pub ocr: OcrConfig,
+    pub window: WindowConfig,
+    pub save: SaveConfig,
+    pub history: HistoryConfig,
+    /// 贴图窗口快捷键
+    pub keymap: Keymap,
+    pub bindings: Vec<Binding>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// 小于该尺寸的选区不截图
+    pub min_width: u32,
+    pub min_height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GestureConfig {
+    /// 松开前移动距离小于该值视为单击，像素
+    pub click_distance: f64,
+    /// 按住时间短于该值视为单击，毫秒
+    pub click_timeout: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OcrConfig {
+    /// 图片未携带分辨率时使用的DPI
+    pub dpi: i32,
+    /// tesseract预训练模型目录，找不到模型时再查找TESSDATA_PREFIX与当前目录
+    pub tessdata_dir: PathBuf,
+    /// 未指定语言时使用的语言配置名，为空时使用模型目录下的所有语言
+    pub default_profile: String,
+    /// 命名的语言配置，可在贴图右键菜单或托盘菜单中选用
+    pub profiles: Vec<OcrProfile>,
+    /// 默认的预处理方案名，为空时不做预处理
+    pub preprocess: String,
+    /// 命名的预处理方案，按顺序执行其中的步骤
+    pub presets: Vec<Preset>,
+    /// 设置后把每一步预处理的结果写入该目录，用于调试
+    pub debug_dir: Option<PathBuf>,
+    /// 识别结果格式：text、hocr、tsv、alto、json
+    pub format: OcrFormat,
+    /// 识别结果写入clipboard剪切板、file保存到截图旁或both两者
+    pub output: OcrOutput,
+    /// 识别后保留贴图并标出识别到的词，可拖动选择文字
+    pub show_words: bool,
+    /// 置信度低于该值的词高亮显示，0～100
+    pub low_confidence: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OcrProfile {
+    pub name: String,
+    /// tesseract语言，如chi_sim、eng，多个语言同时识别
+    pub languages: Vec<String>,
+    /// 使用该语言配置时的预处理方案，未设置时使用ocr.preprocess
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preprocess: Option<String>,
+    /// 页面分割模式，0～13，如6单个文本块、7单行、8单词、11稀疏文本，未设置时自动分割
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub psm: Option<u32>,
+    /// 引擎模式：0旧引擎、1 LSTM、2两者、3默认
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oem: Option<u32>,
+    /// 只识别这些字符
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub whitelist: Option<String>,
+    /// 不识别这些字符
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blacklist: Option<String>,
+    /// 覆盖图片分辨率，未设置时使用ocr.dpi
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<i32>,
+    /// 其它tesseract参数，如preserve_interword_spaces = "0"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub name: String,
+    /// upscale[:最小高度]、invert、denoise[:半径]、otsu、adaptive[:半径]、deskew[:最大角度]、pad[:边框]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub always_on_top: bool,
+    pub decorations: bool,
+    pub resizable: bool,
+    pub transparent: bool,
+    /// 拖动时吸附屏幕边缘与其它贴图的距离，像素，0表示不吸附
+    pub snap_distance: u32,
+    /// 方向键移动的距离，按住Shift时为10倍，像素
+    pub nudge_step: u32,
+    /// 在贴图上右键：menu弹出菜单，ocr直接识别
+    pub right_click: RightClick,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RightClick {
+    Menu,
+    Ocr,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SaveConfig {
+    /// 是否自动保存每张截图
+    pub enabled: bool,
+    pub dir: PathBuf,
+    /// 文件名模板，支持{date}、{time}、{seq}、{monitor}、{width}、{height}
+    pub template: String,
+    /// png、jpeg、webp、bmp、tiff
+    pub format: SaveFormat,
+    /// jpeg质量，1～100
+    pub quality: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    /// 历史截图总大小上限，MB，0表示不限
+    pub max_size_mb: u64,
+    /// 保留天数，0表示不限
+    pub max_age_days: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_file: "middle-screenshot.log".to_string(),
+            capture: CaptureConfig::default(),
+            gesture: GestureConfig::default(),
+            ocr: OcrConfig::default(),
+            window: WindowConfig::default(),
+            save: SaveConfig::default(),
+            history: HistoryConfig::default(),
+            keymap: Keymap::default(),
+            bindings: vec![Binding::default()],
+        }
+    }
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            min_width: 10,
+            min_height: 10,
+        }
+    }
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            click_distance: 5.0,
+            click_timeout: 150,
+        }
+    }
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            dpi: 72,
+            tessdata_dir: PathBuf::from("."),
+            default_profile: "简体中文".to_string(),
+            profiles: vec![
+                OcrProfile::new("简体中文", &["chi_sim", "eng"]),
+                OcrProfile::new("英文", &["eng"]),
+                OcrProfile::new("竖排中文", &["chi_sim_vert"]),
+                OcrProfile {
+                    psm: Some(7),
+                    ..OcrProfile::new("单行", &["chi_sim", "eng"])
+                },
+                OcrProfile {
+                    psm: Some(7),
+                    whitelist: Some("0123456789.,+-%".to_string()),
+                    ..OcrProfile::new("数字", &["eng"])
+                },
+            ],
+            preprocess: "默认".to_string(),
+            presets: vec![
+                Preset::new("默认", &[Step::Invert, Step::Adaptive(11), Step::Pad(10)]),
+                Preset::new(
+                    "小字",
+                    &[Step::Upscale(100), Step::Invert, Step::Otsu, Step::Pad(10)],
+                ),
+                Preset::new(
+                    "彩色文字",
+                    &[Step::Denoise(1), Step::Invert, Step::Otsu, Step::Pad(10)],
+                ),
+                Preset::new(
+                    "倾斜",
+                    &[Step::Invert, Step::Otsu, Step::Deskew(10.0), Step::Pad(10)],
+                ),
+                Preset::new("原图", &[]),
+            ],
+            debug_dir: None,
+            format: OcrFormat::Text,
+            output: OcrOutput::Clipboard,
+            show_words: false,
+            low_confidence: 60.0,
+        }
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            always_on_top: true,
+            decorations: false,
+            resizable: false,
+            transparent: true,
+            snap_distance: 10,
+            nudge_step: 1,
+            right_click: RightClick::Menu,
+        }
+    }
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: dirs::picture_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(CONFIG_DIR),
+            template: "screenshot_{date}_{time}_{seq}".to_string(),
+            format: SaveFormat::Png,
+            quality: 90,
+        }
+    }
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size_mb: 500,
+            max_age_days: 30,
+        }
+    }
+}
+
+impl OcrProfile {
+    fn new(name: &str, languages: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            languages: languages.iter().map(|lang| lang.to_string()).collect(),
+            preprocess: None,
+            psm: None,
+            oem: None,
+            whitelist: None,
+            blacklist: None,
+            dpi: None,
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+impl Preset {
+    fn new(name: &str, steps: &[Step]) -> Self {
+        Self {
+            name: name.to_string(),
+            steps: steps.to_vec(),
+        }
+    }
+}
+
+impl OcrConfig {
+    pub fn profile(&self, name: &str) -> Option<&OcrProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn preset(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+}
+
+impl GestureConfig {
+    pub fn click_timeout(&self) -> Duration {
+        Duration::from_millis(self.click_timeout)
+    }
+}
+
+impl Config {
+    /// 校验取值范围，列出所有不合法的配置项
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        if self.log_file.trim().is_empty() {
+            errors.push("log_file不能为空".to_string());
+        }
+        if self.capture.min_width == 0 || self.capture.min_height == 0 {
+            errors.push("capture.min_width、capture.min_height必须大于0".to_string());
+        }
+        if !self.gesture.click_distance.is_finite() || self.gesture.click_distance < 0.0 {
+            errors.push("gesture.click_distance必须是非负数".to_string());
+        }
+        if self.gesture.click_timeout > 5000 {
+            errors.push("gesture.click_timeout不能超过5000毫秒".to_string());
+        }
+        if !(70..=2400).contains(&self.ocr.dpi) {
+            errors.push(format!("ocr.dpi必须在70～2400之间，当前为{}", self.ocr.dpi));
+        }
+        for (i, profile) in self.ocr.profiles.iter().enumerate() {
+            if profile.name.trim().is_empty() {
+                errors.push(format!("ocr.profiles第{}项的name不能为空", i + 1));
+            } else if self.ocr.profiles[..i]
+                .iter()
+                .any(|other| other.name == profile.name)
+            {
+                errors.push(format!("ocr.profiles中的{}重复", profile.name));
+            }
+            if profile.languages.is_empty()
+                || profile
+                    .languages
+                    .iter()
+                    .any(|lang| lang.is_empty() || lang.contains('+'))
+            {
+                errors.push(format!(
+                    "ocr.profiles中{}的languages不能为空，每项为单个语言",
+                    profile.name
+                ));
+            }
+            if profile.psm.is_some_and(|psm| psm > 13) {
+                errors.push(format!(
+                    "ocr.profiles中{}的psm必须在0～13之间",
+                    profile.name
+                ));
+            }
+            if profile.oem.is_some_and(|oem| oem > 3) {
+                errors.push(format!("ocr.profiles中{}的oem必须在0～3之间", profile.name));
+            }
+            if profile.dpi.is_some_and(|dpi| !(70..=2400).contains(&dpi)) {
+                errors.push(format!(
+                    "ocr.profiles中{}的dpi必须在70～2400之间",
+                    profile.name
+                ));
+            }
+            if profile.variables.keys().any(|name| name.trim().is_empty()) {
+                errors.push(format!(
+                    "ocr.profiles中{}的variables参数名不能为空",
+                    profile.name
+                ));
+            }
+            if let Some(preset) = &profile.preprocess {
+                if !preset.is_empty() && self.ocr.preset(preset).is_none() {
+                    errors.push(format!(
+                        "ocr.profiles中{}的preprocess未在ocr.presets中配置：{preset}",
+                        profile.name
+                    ));
+                }
+            }
+        }
+        for (i, preset) in self.ocr.presets.iter().enumerate() {
+            if preset.name.trim().is_empty() {
+                errors.push(format!("ocr.presets第{}项的name不能为空", i + 1));
+            } else if self.ocr.presets[..i]
+                .iter()
+                .any(|other| other.name == preset.name)
+            {
+                errors.push(format!("ocr.presets中的{}重复", preset.name));
+            }
+        }
+        if !self.ocr.preprocess.is_empty() && self.ocr.preset(&self.ocr.preprocess).is_none() {
+            errors.push(format!(
+                "ocr.preprocess未在ocr.presets中配置：{}",
+                self.ocr.preprocess
+            ));
+        }
+        if !self.ocr.default_profile.is_empty()
+            && self.ocr.profile(&self.ocr.default_profile).is_none()
+        {
+            errors.push(format!(
+                "ocr.default_profile未在ocr.profiles中配置：{}",
+                self.ocr.default_profile
+            ));
+        }
+        if !(0.0..=100.0).contains(&self.ocr.low_confidence) {
+            errors.push(format!(
+                "ocr.low_confidence必须在0～100之间，当前为{}",
+                self.ocr.low_confidence
+            ));
+        }
+        if self.window.nudge_step == 0 {
+            errors.push("window.nudge_step必须大于0".to_string());
+        }
+        if let Err(e) = save::check_template(&self.save.template) {
+            errors.push(format!("save.template有误：{e}"));
+        }
+        if !(1..=100).contains(&self.save.quality) {
+            errors.push(format!(
+                "save.quality必须在1～100之间，当前为{}",
+                self.save.quality
+            ));
+        }
+        if self.bindings.is_empty() {
+            errors.push("bindings至少需要一个触发绑定".to_string());
+        }
+        for binding in &self.bindings {
+            if let Some(profile) = &binding.ocr {
+                if self.ocr.profile(profile).is_none() {
+                    errors.push(format!("bindings中的ocr未在ocr.profiles中配置：{profile}"));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(errors.join("；")))
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+}
+
+/// 当前生效的配置
+pub fn current() -> Arc<Config> {
+    CURRENT
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+fn replace(config: Config) {
+    *CURRENT
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(config);
+}
+
+/// 读取并校验配置文件，文件不存在时写入默认配置
+pub fn load() -> Result<Config> {
+    let Some(path) = path() else {
+        return Ok(Config::default());
+    };
+    if !path.exists() {
+        let config = Config::default();
+        save(&path, &config).log_error("写入默认配置失败");
+        return Ok(config);
+    }
+
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("读取配置文件{}失败", path.display()))?;
+    let config: Config =
+        toml::from_str(&content).with_context(|| format!("解析配置文件{}失败", path.display()))?;
+    config
+        .validate()
+        .with_context(|| format!("配置文件{}有误", path.display()))?;
+    Ok(config)
+}
+
+fn save(path: &Path, config: &Config) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string_pretty(config)?)?;
+    Ok(())
+}
+
+/// 启动时加载配置，出错时使用默认配置并返回错误
+pub fn init() -> Result<()> {
+    match load() {
+        Ok(config) => {
+            replace(config);
+            Ok(())
+        }
+        Err(e) => {
+            replace(Config::default());
+            Err(e)
+        }
+    }
+}
+
+/// 监听配置文件变更，校验通过后替换当前配置并通知事件循环
+pub fn watch(event_tx: EventLoopProxy<Event>) -> Result<RecommendedWatcher> {
+    let Some(path) = path() else {
+        bail!("找不到用户配置目录");
+    };
+    let Some(dir) = path.parent().map(Path::to_path_buf) else {
+        bail!("找不到用户配置目录");
+    };
+    fs::create_dir_all(&dir)?;
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            || !event.paths.iter().any(|p| p.ends_with(CONFIG_FILE))
+        {
+            return;
+        }
+        match load() {
+            Ok(config) if config != *current() => {
+                info!("配置已更新");
+                replace(config);
+                event_tx
+                    .send_event(Event::ConfigChanged)
+                    .log_error("发送配置更新事件失败");
+            }
+            Ok(_) => {}
+            Err(e) => error!("{e:#}，继续使用原配置"),
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
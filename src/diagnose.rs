@@ -0,0 +1,119 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+
+use crate::{config, ocr, render, util};
+
+/// OCR模型检查未通过时禁用OCR，检查前视为可用
+static OCR_AVAILABLE: AtomicBool = AtomicBool::new(true);
+
+/// 自检发现的问题
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// 检查项
+    pub item: &'static str,
+    pub message: String,
+}
+
+/// 启动时在后台自检一次，有问题时发送通知，OCR可用时预加载模型
+pub fn startup() {
+    thread::spawn(|| {
+        let problems = run();
+        if !problems.is_empty() {
+            util::notify(&format!(
+                "{}\n可在托盘菜单“诊断”中重新检查",
+                summary(&problems)
+            ));
+        }
+        if ocr_available() {
+            ocr::warm_up();
+        }
+    });
+}
+
+/// 托盘菜单“诊断”：重新自检并通知结果
+pub fn report() {
+    thread::spawn(|| {
+        let problems = run();
+        if problems.is_empty() {
+            util::notify("自检未发现问题");
+        } else {
+            util::notify(&summary(&problems));
+        }
+    });
+}
+
+/// OCR不可用时通知并返回错误
+pub fn check_ocr() -> Result<()> {
+    if ocr_available() {
+        return Ok(());
+    }
+    util::notify("OCR不可用，请检查tesseract预训练模型，详见托盘菜单“诊断”");
+    Err(anyhow!("OCR已禁用"))
+}
+
+pub fn ocr_available() -> bool {
+    OCR_AVAILABLE.load(Ordering::Relaxed)
+}
+
+/// 检查OCR模型目录与模型、图标、中文字体与图形适配器，按结果启用或禁用OCR
+fn run() -> Vec<Problem> {
+    let mut problems = Vec::new();
+    // 目录不存在不影响加载配置，模型仍可从其它目录找到
+    let tessdata_dir = config::current().ocr.tessdata_dir.clone();
+    if !tessdata_dir.is_dir() {
+        problems.push(Problem {
+            item: "OCR模型目录",
+            message: format!("ocr.tessdata_dir目录不存在：{}", tessdata_dir.display()),
+        });
+    }
+    match ocr::resolve(None) {
+        Ok((dir, lang)) => {
+            info!("OCR模型：{lang}（{}）", dir.display());
+            OCR_AVAILABLE.store(true, Ordering::Relaxed);
+        }
+        Err(e) => {
+            OCR_AVAILABLE.store(false, Ordering::Relaxed);
+            problems.push(Problem {
+                item: "OCR模型",
+                message: format!("{e:#}，OCR已禁用"),
+            });
+        }
+    }
+    if let Err(e) = util::load_icon() {
+        problems.push(Problem {
+            item: "图标",
+            message: format!("{e}，使用内置图标"),
+        });
+    }
+    match render::font_name() {
+        Ok(name) => info!("标签字体：{name}"),
+        Err(e) => problems.push(Problem {
+            item: "中文字体",
+            message: format!("{e:#}，贴图上识别到的文字无法显示"),
+        }),
+    }
+    match render::probe() {
+        Ok(adapter) => info!("图形适配器：{adapter}"),
+        Err(e) => problems.push(Problem {
+            item: "图形适配器",
+            message: format!("{e:#}，贴图改用CPU绘制"),
+        }),
+    }
+    for problem in &problems {
+        warn!("自检：{}：{}", problem.item, problem.message);
+    }
+    problems
+}
+
+fn summary(problems: &[Problem]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("{}：{}", problem.item, problem.message))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
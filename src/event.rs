@@ -1,12 +1,64 @@
 use winit::window::WindowId;
 
-#[derive(Debug, PartialEq)]
+use crate::{lens::Lens, output::OcrResult};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Event {
-    Start,
+    /// 开始选区，附带绑定指定的OCR语言配置
+    Start(f64, f64, Option<String>),
     Move(f64, f64),
     End,
+    /// 选区窗口隐藏后截图，附带绑定指定的OCR语言配置
+    Capture(Lens, Option<String>),
+    Cancel,
     Pause,
     Resume,
-    Close(WindowId),
+    ConfigChanged,
+    ShowHistory,
+    HistoryChanged,
+    /// 识别上次截图，可指定语言配置
+    OcrLast(Option<String>),
+    ClickThroughOff,
+    /// 重新自检并通知结果
+    Diagnose,
+    /// 贴图操作及目标贴图，来自菜单时为None，作用于弹出菜单的贴图或最近操作的贴图
+    Pin(Option<WindowId>, PinAction),
+    /// 贴图识别完成，附带识别编号；确认是进行中的识别后输出结果，按发起时的配置保留贴图标出识别到的词或关闭窗口
+    OcrDone(WindowId, u64, OcrResult),
+    /// 上次截图或历史记录识别完成，附带识别编号；确认未被新的识别取代后输出结果
+    OcrLastDone(u64, OcrResult),
+    /// 识别失败或已取消，停止动画
+    OcrFailed(WindowId, u64),
     Redraw(WindowId),
 }
+
+/// 贴图操作，来自右键菜单或快捷键
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinAction {
+    Ocr,
+    /// 按指定的语言配置识别
+    OcrWith(String),
+    /// 按指定的预处理方案识别
+    OcrPreset(String),
+    /// 取消进行中的识别
+    CancelOcr,
+    /// 有选中文字时复制文字，否则复制图片
+    Copy,
+    CopyImage,
+    /// 复制选中文字，未选择时复制全部识别文字
+    CopyText,
+    /// 按模板保存到截图目录
+    Save,
+    /// 弹出另存为对话框
+    SaveAs,
+    Zoom(f32),
+    ZoomIn,
+    ZoomOut,
+    Opacity(f32),
+    ClickThrough,
+    /// 撤销或重做位置、缩放与不透明度的调整
+    Undo,
+    Redo,
+    Close,
+    CloseAll,
+}
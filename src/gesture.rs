@@ -0,0 +1,267 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use log_error::LogError;
+use rdev::EventType;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    binding::{Chord, Modifiers, Trigger},
+    config::{self, Config},
+    event::Event,
+};
+
+lazy_static! {
+    /// 重放的模拟事件也会经过钩子，只放行与之相同的事件
+    static ref REPLAYING: Mutex<Vec<EventType>> = Mutex::new(Vec::new());
+}
+
+enum State {
+    Idle,
+    /// 按住触发键，尚未移动
+    Pressed {
+        trigger: Trigger,
+        at: Instant,
+        origin: (f64, f64),
+    },
+    /// 按住触发键拖动中
+    Dragging {
+        trigger: Trigger,
+        at: Instant,
+        origin: (f64, f64),
+    },
+    /// 按start开始、按end结束，held表示start键尚未松开
+    Toggled {
+        start: Trigger,
+        end: Chord,
+        held: bool,
+    },
+}
+
+/// 按绑定识别截图手势，单击原样重放给系统，拖动才截图
+pub struct Gesture {
+    /// 空闲时按下按键才刷新，避免手势进行中配置变化
+    config: Arc<Config>,
+    state: State,
+    position: (f64, f64),
+    modifiers: Modifiers,
+    /// 已拦截按下、需要同时拦截松开的按键
+    pending_release: Option<Trigger>,
+    /// 当前手势所属绑定指定的OCR语言配置
+    ocr: Option<String>,
+}
+
+impl Gesture {
+    pub fn new() -> Self {
+        Self {
+            config: config::current(),
+            state: State::Idle,
+            position: (0.0, 0.0),
+            modifiers: Modifiers::default(),
+            pending_release: None,
+            ocr: None,
+        }
+    }
+
+    /// 处理钩子事件，返回true表示拦截该事件
+    pub fn handle(&mut self, event_type: &EventType, event_tx: &EventLoopProxy<Event>) -> bool {
+        let (trigger, pressed) = match *event_type {
+            EventType::MouseMove { x, y } => {
+                self.on_move(x, y, event_tx);
+                return false;
+            }
+            EventType::ButtonPress(button) => (Trigger::Button(button), true),
+            EventType::ButtonRelease(button) => (Trigger::Button(button), false),
+            EventType::KeyPress(key) => (Trigger::Key(key), true),
+            EventType::KeyRelease(key) => (Trigger::Key(key), false),
+            _ => return false,
+        };
+
+        let swallow = if take_replayed(event_type) {
+            false
+        } else if pressed {
+            self.on_press(trigger, event_tx)
+        } else {
+            self.on_release(trigger, event_tx)
+        };
+        self.modifiers.update(&trigger, pressed);
+        swallow
+    }
+
+    fn on_move(&mut self, x: f64, y: f64, event_tx: &EventLoopProxy<Event>) {
+        self.position = (x, y);
+        if let State::Pressed {
+            trigger,
+            at,
+            origin,
+        } = self.state
+        {
+            if distance(origin, self.position) >= self.config.gesture.click_distance {
+                self.state = State::Dragging {
+                    trigger,
+                    at,
+                    origin,
+                };
+                send(event_tx, Event::Start(origin.0, origin.1, self.ocr.clone()));
+            }
+        }
+        send(event_tx, Event::Move(x, y));
+    }
+
+    fn on_press(&mut self, trigger: Trigger, event_tx: &EventLoopProxy<Event>) -> bool {
+        match self.state {
+            State::Idle => {
+                self.config = config::current();
+                let Some(binding) = self
+                    .config
+                    .bindings
+                    .iter()
+                    .find(|binding| binding.start.matches(&trigger, &self.modifiers))
+                else {
+                    return false;
+                };
+                self.ocr = binding.ocr.clone();
+                self.state = match binding.end {
+                    None => State::Pressed {
+                        trigger,
+                        at: Instant::now(),
+                        origin: self.position,
+                    },
+                    Some(end) => {
+                        let (x, y) = self.position;
+                        send(event_tx, Event::Start(x, y, self.ocr.clone()));
+                        State::Toggled {
+                            start: trigger,
+                            end,
+                            held: true,
+                        }
+                    }
+                };
+                true
+            }
+            // 按住期间键盘的自动重复
+            State::Pressed { trigger: held, .. } | State::Dragging { trigger: held, .. }
+                if held == trigger =>
+            {
+                true
+            }
+            State::Toggled {
+                start, held: true, ..
+            } if start == trigger => true,
+            State::Toggled { end, .. } if end.matches(&trigger, &self.modifiers) => {
+                self.state = State::Idle;
+                self.pending_release = Some(trigger);
+                send(event_tx, Event::End);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn on_release(&mut self, trigger: Trigger, event_tx: &EventLoopProxy<Event>) -> bool {
+        if self.pending_release == Some(trigger) {
+            self.pending_release = None;
+            return true;
+        }
+        match self.state {
+            State::Pressed {
+                trigger: held,
+                origin,
+                ..
+            } if held == trigger => {
+                self.state = State::Idle;
+                replay(trigger, origin, self.position);
+                true
+            }
+            State::Dragging {
+                trigger: held,
+                at,
+                origin,
+            } if held == trigger => {
+                self.state = State::Idle;
+                if at.elapsed() < self.config.gesture.click_timeout() {
+                    send(event_tx, Event::Cancel);
+                    replay(trigger, origin, self.position);
+                } else {
+                    send(event_tx, Event::End);
+                }
+                true
+            }
+            State::Toggled {
+                start,
+                ref mut held,
+                ..
+            } if start == trigger && *held => {
+                *held = false;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn send(event_tx: &EventLoopProxy<Event>, event: Event) {
+    event_tx.send_event(event).log_error("发送鼠标事件失败");
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn replaying() -> MutexGuard<'static, Vec<EventType>> {
+    REPLAYING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// 是重放的事件时取出并返回true
+fn take_replayed(event_type: &EventType) -> bool {
+    let mut replaying = replaying();
+    match replaying.iter().position(|replayed| replayed == event_type) {
+        Some(index) => {
+            replaying.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 重放被拦截的单击，不能在钩子回调里直接模拟；
+/// 鼠标按键先把光标移回按下处再点击，之后移回position
+fn replay(trigger: Trigger, origin: (f64, f64), position: (f64, f64)) {
+    let mut events = vec![trigger.press(), trigger.release()];
+    if matches!(trigger, Trigger::Button(_)) && origin != position {
+        events.insert(
+            0,
+            EventType::MouseMove {
+                x: origin.0,
+                y: origin.1,
+            },
+        );
+        events.push(EventType::MouseMove {
+            x: position.0,
+            y: position.1,
+        });
+    }
+    thread::spawn(move || {
+        for event_type in events {
+            // 移动事件不会被拦截，无需放行
+            let moving = matches!(event_type, EventType::MouseMove { .. });
+            if !moving {
+                replaying().push(event_type);
+            }
+            if rdev::simulate(&event_type)
+                .log_error("重放单击失败")
+                .is_none()
+                && !moving
+            {
+                take_replayed(&event_type);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+}
@@ -0,0 +1,249 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Context, Result};
+use lazy_static::lazy_static;
+use log_error::LogError;
+use screenshots::Image;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::{
+    config::{self, HistoryConfig},
+    lens::Lens,
+    output::OcrFormat,
+};
+
+const HISTORY_DIR: &str = "middle-screenshot/history";
+const INDEX_FILE: &str = "history.toml";
+
+lazy_static! {
+    static ref INDEX: Mutex<Index> = Mutex::new(load_index());
+}
+
+/// 一条截图历史，图片以{id}.png保存在历史目录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    /// unix时间戳，秒
+    pub time: i64,
+    pub lens: Lens,
+    /// png文件大小，字节
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+impl Entry {
+    fn path(&self) -> Option<PathBuf> {
+        image_path(self.id)
+    }
+
+    /// 已登记但图片尚未写入
+    fn pending(&self) -> bool {
+        self.bytes == 0
+    }
+}
+
+fn image_path(id: u64) -> Option<PathBuf> {
+    dir().map(|dir| dir.join(format!("{id}.png")))
+}
+
+fn dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join(HISTORY_DIR))
+}
+
+fn load_index() -> Index {
+    let Some(path) = dir().map(|dir| dir.join(INDEX_FILE)) else {
+        return Index::default();
+    };
+    if !path.exists() {
+        return Index::default();
+    }
+    fs::read_to_string(&path)
+        .map_err(|e| anyhow!(e))
+        .and_then(|content| toml::from_str(&content).map_err(|e| anyhow!(e)))
+        .unwrap_or_else(|e| {
+            error!("读取历史记录{}失败，重新记录：{e}", path.display());
+            Index::default()
+        })
+}
+
+fn lock() -> MutexGuard<'static, Index> {
+    INDEX
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Index {
+    fn save(&self) -> Result<()> {
+        let dir = dir().ok_or_else(|| anyhow!("找不到用户数据目录"))?;
+        fs::create_dir_all(&dir)?;
+        // 图片尚未写入的记录不保存
+        let saved = Index {
+            entries: self
+                .entries
+                .iter()
+                .filter(|entry| !entry.pending())
+                .cloned()
+                .collect(),
+        };
+        fs::write(dir.join(INDEX_FILE), toml::to_string(&saved)?)?;
+        Ok(())
+    }
+
+    /// 按保留策略删除过期或超出总大小的旧记录
+    fn prune(&mut self, config: &HistoryConfig) {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let max_age = config.max_age_days as i64 * 24 * 60 * 60;
+        let max_bytes = config.max_size_mb * 1024 * 1024;
+
+        // 新记录在后，从新到旧累计大小
+        let mut total = 0;
+        let mut keep = vec![true; self.entries.len()];
+        for (i, entry) in self.entries.iter().enumerate().rev() {
+            total += entry.bytes;
+            let expired = max_age > 0 && now - entry.time > max_age;
+            let oversize = max_bytes > 0 && total > max_bytes;
+            keep[i] = !expired && !oversize;
+        }
+        let mut keep = keep.into_iter();
+        self.entries.retain(|entry| {
+            let keep = keep.next().unwrap_or(true);
+            if !keep {
+                remove_file(entry);
+            }
+            keep
+        });
+    }
+}
+
+fn remove_file(entry: &Entry) {
+    if let Some(path) = entry.path() {
+        fs::remove_file(path).log_error("删除历史截图失败");
+    }
+    // 同时删除保存在截图旁的OCR结果
+    for format in OcrFormat::ALL {
+        if let Some(path) = sidecar(entry.id, format.extension()).filter(|path| path.exists()) {
+            fs::remove_file(path).log_error("删除OCR结果失败");
+        }
+    }
+}
+
+/// 登记一次截图，未开启历史记录时返回None；图片由save_image写入后记录才会显示并写入索引
+pub fn record(lens: &Lens) -> Option<u64> {
+    if !config::current().history.enabled {
+        return None;
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let mut index = lock();
+    // 以毫秒时间戳为id，同一毫秒内顺延
+    let mut id = (now.unix_timestamp_nanos() / 1_000_000) as u64;
+    if let Some(last) = index.entries.last() {
+        id = id.max(last.id + 1);
+    }
+    index.entries.push(Entry {
+        id,
+        time: now.unix_timestamp(),
+        lens: *lens,
+        bytes: 0,
+        text: None,
+    });
+    Some(id)
+}
+
+/// 写入登记时的截图png，写入失败时删除该记录
+pub fn save_image(id: u64, png: &[u8]) -> Result<()> {
+    // 写文件时不持有索引锁，避免阻塞界面线程
+    let written = image_path(id)
+        .ok_or_else(|| anyhow!("找不到用户数据目录"))
+        .and_then(|path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, png).with_context(|| format!("写入历史截图{}失败", path.display()))
+        });
+    if let Err(e) = written {
+        discard(id);
+        return Err(e);
+    }
+
+    let mut index = lock();
+    let Some(entry) = index.entries.iter_mut().find(|entry| entry.id == id) else {
+        return Ok(());
+    };
+    entry.bytes = png.len() as u64;
+    index.prune(&config::current().history);
+    index.save()
+}
+
+/// 删除尚未写入图片的记录
+pub fn discard(id: u64) {
+    lock()
+        .entries
+        .retain(|entry| entry.id != id || !entry.pending());
+}
+
+/// 记录OCR结果
+pub fn set_text(id: u64, text: &str) -> Result<()> {
+    let mut index = lock();
+    let Some(entry) = index.entries.iter_mut().find(|entry| entry.id == id) else {
+        return Ok(());
+    };
+    entry.text = Some(text.to_string());
+    index.save()
+}
+
+/// 所有图片已写入的历史记录，新的在前
+pub fn entries() -> Vec<Entry> {
+    lock()
+        .entries
+        .iter()
+        .rev()
+        .filter(|entry| !entry.pending())
+        .cloned()
+        .collect()
+}
+
+pub fn get(id: u64) -> Option<Entry> {
+    lock().entries.iter().find(|entry| entry.id == id).cloned()
+}
+
+/// 历史截图旁的同名文件，如OCR结果
+pub fn sidecar(id: u64, extension: &str) -> Option<PathBuf> {
+    dir().map(|dir| dir.join(format!("{id}.{extension}")))
+}
+
+/// 读取历史截图
+pub fn image(id: u64) -> Result<Image> {
+    let entry = get(id).ok_or_else(|| anyhow!("历史记录{id}不存在"))?;
+    let path = entry.path().ok_or_else(|| anyhow!("找不到用户数据目录"))?;
+    let image = ::image::open(&path)
+        .with_context(|| format!("读取历史截图{}失败", path.display()))?
+        .into_rgba8();
+    Ok(Image::new(image.width(), image.height(), image.into_raw()))
+}
+
+pub fn remove(id: u64) -> Result<()> {
+    let mut index = lock();
+    index.entries.retain(|entry| {
+        if entry.id == id {
+            remove_file(entry);
+            false
+        } else {
+            true
+        }
+    });
+    index.save()
+}
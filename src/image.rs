@@ -1,14 +1,15 @@
 use std::io::Cursor;
 
-use anyhow::{Ok, Result};
-use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
-use imageproc::contrast::adaptive_threshold;
+use anyhow::{anyhow, Ok, Result};
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgb, Rgba, RgbaImage};
 use screenshots::Image;
 
 pub trait ImageExt {
     fn rgb(&self) -> Vec<u8>;
     fn to_bmp(&self) -> Result<Vec<u8>>;
-    fn to_tiff(&self) -> Result<Vec<u8>>;
+    fn to_png(&self) -> Result<Vec<u8>>;
+    fn encode(&self, format: ImageOutputFormat) -> Result<Vec<u8>>;
+    fn to_rgba_image(&self) -> Result<RgbaImage>;
 }
 
 impl ImageExt for Image {
@@ -25,17 +26,36 @@ impl ImageExt for Image {
         Ok(bmp)
     }
 
-    /// 转tiff， On windows, leptonica will only read tiff formatted files from memory.
-    fn to_tiff(&self) -> Result<Vec<u8>> {
-        let rgba: Option<ImageBuffer<Rgba<u8>, Vec<u8>>> =
-            ImageBuffer::from_vec(self.width(), self.height(), self.rgba().to_vec());
-        let mut tiff: Vec<u8> = Vec::new();
-        if let Some(rgba) = rgba {
-            let img = DynamicImage::from(rgba);
-            let img = adaptive_threshold(&img.to_luma8(), 11);
-            img.write_to(&mut Cursor::new(&mut tiff), image::ImageOutputFormat::Tiff)?;
+    /// 转png，保留alpha通道
+    fn to_png(&self) -> Result<Vec<u8>> {
+        self.encode(ImageOutputFormat::Png)
+    }
+
+    /// 按指定格式编码，jpeg与bmp不支持alpha通道，先转rgb
+    fn encode(&self, format: ImageOutputFormat) -> Result<Vec<u8>> {
+        let img = match format {
+            ImageOutputFormat::Jpeg(_) | ImageOutputFormat::Bmp => {
+                ImageBuffer::<Rgb<u8>, Vec<u8>>::from_vec(self.width(), self.height(), self.rgb())
+                    .map(DynamicImage::from)
+            }
+            _ => ImageBuffer::<Rgba<u8>, Vec<u8>>::from_vec(
+                self.width(),
+                self.height(),
+                self.rgba().to_vec(),
+            )
+            .map(DynamicImage::from),
+        };
+        let mut data: Vec<u8> = Vec::new();
+        if let Some(img) = img {
+            img.write_to(&mut Cursor::new(&mut data), format)?;
         }
-        Ok(tiff)
+        Ok(data)
+    }
+
+    /// 转为image库的图片，供OCR预处理
+    fn to_rgba_image(&self) -> Result<RgbaImage> {
+        ImageBuffer::from_vec(self.width(), self.height(), self.rgba().to_vec())
+            .ok_or_else(|| anyhow!("截图数据与尺寸不符"))
     }
 
     fn rgb(&self) -> Vec<u8> {
@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+/// 截图区域，虚拟桌面物理像素坐标，副屏位于主屏左侧或上方时原点为负
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Lens {
     pub x: i32,
     pub y: i32,
@@ -9,42 +13,20 @@ impl Lens {
     pub fn from(start_point: (f64, f64), end_point: (f64, f64)) -> Self {
         let x_v = end_point.0 - start_point.0;
         let y_v = end_point.1 - start_point.1;
-
-        let x;
-        let y;
-        let width;
-        let height;
-        if x_v > 0.0 && y_v > 0.0 {
-            x = start_point.0;
-            y = start_point.1;
-            width = x_v;
-            height = y_v;
-        } else if x_v < 0.0 && y_v < 0.0 {
-            x = end_point.0;
-            y = end_point.1;
-            width = x_v.abs();
-            height = y_v.abs();
-        } else if x_v > 0.0 && y_v < 0.0 {
-            x = start_point.0;
-            y = end_point.1;
-            width = x_v;
-            height = y_v.abs();
-        } else if x_v < 0.0 && y_v > 0.0 {
-            x = end_point.0;
-            y = start_point.1;
-            width = x_v.abs();
-            height = y_v;
-        } else {
-            x = 0.0;
-            y = 0.0;
-            width = 0.0;
-            height = 0.0;
+        if x_v == 0.0 || y_v == 0.0 {
+            return Self {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            };
         }
 
-        let x = x.max(0.0) as i32;
-        let y = y.max(0.0) as i32;
-        let width = width as u32;
-        let height = height as u32;
+        // 任意拖动方向都取左上角为原点，不对负坐标做截断
+        let x = start_point.0.min(end_point.0).floor() as i32;
+        let y = start_point.1.min(end_point.1).floor() as i32;
+        let width = x_v.abs() as u32;
+        let height = y_v.abs() as u32;
 
         Self {
             x,
@@ -53,4 +35,95 @@ impl Lens {
             height,
         }
     }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    /// 两区域的交集，不相交时返回None
+    pub fn intersect(&self, other: &Lens) -> Option<Lens> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Lens {
+            x,
+            y,
+            width: (right - x) as u32,
+            height: (bottom - y) as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lens(x: i32, y: i32, width: u32, height: u32) -> Lens {
+        Lens {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn drag_to_top_left() {
+        assert_eq!(
+            Lens::from((-100.0, -50.0), (-300.0, -250.0)),
+            lens(-300, -250, 200, 200)
+        );
+    }
+
+    #[test]
+    fn drag_to_top_right() {
+        assert_eq!(
+            Lens::from((-100.0, -50.0), (20.0, -250.0)),
+            lens(-100, -250, 120, 200)
+        );
+    }
+
+    #[test]
+    fn drag_to_bottom_left() {
+        assert_eq!(
+            Lens::from((-100.0, -50.0), (-300.0, 30.0)),
+            lens(-300, -50, 200, 80)
+        );
+    }
+
+    #[test]
+    fn drag_to_bottom_right() {
+        assert_eq!(
+            Lens::from((-100.5, -50.5), (-10.5, -0.5)),
+            lens(-101, -51, 90, 50)
+        );
+    }
+
+    #[test]
+    fn zero_size() {
+        assert_eq!(
+            Lens::from((-100.0, -50.0), (-100.0, 30.0)),
+            lens(0, 0, 0, 0)
+        );
+        assert_eq!(Lens::from((-100.0, -50.0), (20.0, -50.0)), lens(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn intersect() {
+        let monitor = lens(-1920, 0, 1920, 1080);
+        assert_eq!(
+            lens(-100, 1000, 200, 200).intersect(&monitor),
+            Some(lens(-100, 1000, 100, 80))
+        );
+        assert_eq!(lens(0, 0, 100, 100).intersect(&monitor), None);
+        assert_eq!(lens(-2100, -300, 100, 100).intersect(&monitor), None);
+    }
 }
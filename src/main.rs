@@ -1,63 +1,70 @@
 #![windows_subsystem = "windows"]
 use std::{
+    cell::RefCell,
     sync::atomic::{AtomicBool, Ordering},
     thread,
+    time::Duration,
 };
 
 use anyhow::Result;
 use event::Event;
 use log_error::*;
-use rdev::{Button, EventType};
 use screenshots::Image;
 use time::{macros::format_description, UtcOffset};
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::fmt::time::OffsetTime;
-use tray_icon::{TrayIconBuilder, TrayIconEvent};
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem},
+    ClickType, TrayIconBuilder, TrayIconEvent,
+};
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::{ElementState, MouseButton, WindowEvent},
     event_loop::{EventLoopBuilder, EventLoopProxy},
 };
 
+use crate::config::RightClick;
+use crate::gesture::Gesture;
+use crate::image::ImageExt;
 use crate::lens::Lens;
+use crate::menu::{MenuItems, Menus};
+use crate::ocr::Request;
 use crate::windows::Windows;
 
+mod binding;
 mod capture;
+mod clipboard;
+mod config;
+mod diagnose;
 mod event;
+mod gesture;
+mod history;
 mod image;
 mod lens;
+mod menu;
+mod ocr;
+mod output;
+mod preprocess;
 mod render;
+mod save;
+mod tesseract;
 mod util;
 mod windows;
 
-const MIN_WIDTH: u32 = 10;
-const MIN_HEIGHT: u32 = 10;
 static PAUSED: AtomicBool = AtomicBool::new(false);
+/// 隐藏选区窗口后等待合成器刷新画面再截图
+const OVERLAY_HIDE_DELAY: Duration = Duration::from_millis(50);
 
 /// 拦截鼠标事件
 fn listen(event_tx: EventLoopProxy<Event>) {
+    // 回调只在钩子线程中调用
+    let gesture = RefCell::new(Gesture::new());
     rdev::grab(move |event| {
         if PAUSED.load(Ordering::Relaxed) {
             return Some(event);
         }
-        let event_mapper = match event.event_type {
-            EventType::ButtonPress(Button::Middle) => Some(Event::Start),
-            EventType::ButtonRelease(Button::Middle) => Some(Event::End),
-            // EventType::KeyPress(Key::ControlRight) => Some(Event::Start),
-            // EventType::KeyRelease(Key::ControlRight) => Some(Event::End),
-            EventType::MouseMove { x, y } => Some(Event::Move(x, y)),
-            _ => None,
-        };
-        if let Some(mouse_event) = event_mapper {
-            let bool = mouse_event == Event::Start || mouse_event == Event::End;
-            event_tx
-                .send_event(mouse_event)
-                .log_error("发送鼠标事件失败");
-            if bool {
-                None
-            } else {
-                Some(event)
-            }
+        if gesture.borrow_mut().handle(&event.event_type, &event_tx) {
+            None
         } else {
             Some(event)
         }
@@ -65,9 +72,12 @@ fn listen(event_tx: EventLoopProxy<Event>) {
     .log_error_with(|e| format!("鼠标监听失败{e:?}"));
 }
 
-/// 暂停或恢复
+/// 左键单击托盘图标暂停或恢复
 fn pause_or_resume(event_tx: EventLoopProxy<Event>) {
-    while TrayIconEvent::receiver().recv().is_ok() {
+    while let Ok(event) = TrayIconEvent::receiver().recv() {
+        if event.click_type != ClickType::Left {
+            continue;
+        }
         if PAUSED.swap(false, Ordering::Relaxed) {
             info!("恢复");
             event_tx
@@ -83,17 +93,60 @@ fn pause_or_resume(event_tx: EventLoopProxy<Event>) {
     }
 }
 
-// 截图并保存剪切板
-fn screenshot(lens: &Lens) -> Result<Image> {
+/// 托盘菜单
+fn tray_menu(event_tx: EventLoopProxy<Event>, items: MenuItems) {
+    while let Ok(event) = MenuEvent::receiver().recv() {
+        let event = items
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .find(|(id, _)| *id == event.id)
+            .map(|(_, event)| event.clone());
+        if let Some(event) = event {
+            event_tx.send_event(event).log_error("发送托盘菜单事件失败");
+        }
+    }
+}
+
+// 截图并保存剪切板，返回截图与历史记录id；历史截图与自动保存的文件在后台写入，写入后刷新历史记录
+fn screenshot(lens: &Lens, event_loop: EventLoopProxy<Event>) -> Result<(Image, Option<u64>)> {
     let image = util::screenshot(lens)?;
     util::copy_image(&image)?;
-    Ok(image)
+    let history = history::record(lens);
+
+    let (image_to_save, lens) = (image.clone(), *lens);
+    thread::spawn(move || {
+        // 只编码一次png，历史记录与png格式的自动保存共用
+        let png = image_to_save.to_png().log_error("编码截图失败");
+        if let Some(id) = history {
+            match &png {
+                Some(png) => {
+                    history::save_image(id, png).log_error("记录历史失败");
+                }
+                None => history::discard(id),
+            }
+            event_loop
+                .send_event(Event::HistoryChanged)
+                .log_error("刷新历史记录失败");
+        }
+        let config = config::current();
+        if let Some(path) = save::auto_save(&image_to_save, png.as_deref(), &lens, &config.save)
+            .log_error("保存截图失败")
+            .flatten()
+        {
+            info!("截图已保存至{}", path.display());
+        }
+    });
+    Ok((image, history))
 }
 
 fn main() -> Result<()> {
-    let file_appender = tracing_appender::rolling::never(".", "middle-screenshot.log");
+    let config_result = config::init();
+    let mut config = config::current();
+
+    let file_appender = tracing_appender::rolling::never(".", &config.log_file);
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-    let offset = UtcOffset::current_local_offset().expect("should get local offset!");
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
     let timer = OffsetTime::new(
         offset,
         format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
@@ -104,26 +157,69 @@ fn main() -> Result<()> {
         .with_ansi(false)
         .init();
 
+    if let Err(e) = config_result {
+        error!("{e:#}，使用默认配置");
+    }
+
+    diagnose::startup();
+
     let event_loop = EventLoopBuilder::<Event>::with_user_event().build();
+    let history_item = MenuItem::new("历史记录", true, None);
+    let ocr_last_item = MenuItem::new("识别上次截图", true, None);
+    let click_through_item = MenuItem::new("取消贴图鼠标穿透", true, None);
+    let diagnose_item = MenuItem::new("诊断", true, None);
+    let tray_menu_items = Menu::new();
+    tray_menu_items.append_items(&[
+        &history_item,
+        &ocr_last_item,
+        &click_through_item,
+        &diagnose_item,
+    ])?;
+    let mut menus = Menus::new(
+        tray_menu_items.clone(),
+        2,
+        vec![
+            (history_item.id(), Event::ShowHistory),
+            (ocr_last_item.id(), Event::OcrLast(None)),
+            (click_through_item.id(), Event::ClickThroughOff),
+            (diagnose_item.id(), Event::Diagnose),
+        ],
+    )?;
     let tray_icon = TrayIconBuilder::new()
         .with_tooltip("中键截屏")
         .with_icon(util::get_tray_icon()?)
+        .with_menu(Box::new(tray_menu_items))
+        .with_menu_on_left_click(false)
         .build()?;
 
     let mouse_event_tx: EventLoopProxy<Event> = event_loop.create_proxy();
     let tray_event_tx = mouse_event_tx.clone();
     let window_event_tx = mouse_event_tx.clone();
+    let menu_event_tx = mouse_event_tx.clone();
+    let capture_event_tx = mouse_event_tx.clone();
+    let menu_items = menus.items();
+    let _watcher = config::watch(mouse_event_tx.clone()).log_error("监听配置文件失败");
     let _mouse_handle = thread::spawn(|| listen(mouse_event_tx));
     let _tray_handle = thread::spawn(|| pause_or_resume(tray_event_tx));
+    let _menu_handle = thread::spawn(move || tray_menu(menu_event_tx, menu_items));
 
     let mut position = (0.0f64, 0.0f64);
     let mut start_point = None;
+    let mut capture_ocr = None;
     let mut windows = Windows::new(window_event_tx);
 
     event_loop.run(move |event, event_loop, control_flow| {
         control_flow.set_wait();
 
         match event {
+            winit::event::Event::WindowEvent {
+                window_id,
+                ref event,
+            } if windows.is_gallery(&window_id) && *event != WindowEvent::CloseRequested => {
+                windows
+                    .gallery_input(event_loop, event, &config.window)
+                    .log_error("历史记录操作失败");
+            }
             winit::event::Event::WindowEvent {
                 window_id,
                 event: WindowEvent::CloseRequested,
@@ -140,43 +236,96 @@ fn main() -> Result<()> {
                         ..
                     },
                 ..
+            } => match config.window.right_click {
+                RightClick::Menu => {
+                    windows
+                        .show_menu(&window_id, menus.pin())
+                        .log_error("弹出菜单失败");
+                }
+                RightClick::Ocr => {
+                    windows
+                        .ocr(&window_id, Request::default())
+                        .log_error("OCR失败");
+                }
+            },
+            winit::event::Event::WindowEvent {
+                window_id,
+                ref event,
             } => {
-                windows.ocr(&window_id).log_error("OCR失败");
+                windows
+                    .pin_input(event_loop, &window_id, event, &config)
+                    .log_error("移动贴图失败");
             }
             winit::event::Event::UserEvent(event) => match event {
-                Event::Start => {
+                Event::Start(x, y, ocr) => {
                     if start_point.is_none() {
-                        start_point = Some(position);
+                        start_point = Some((x, y));
+                        capture_ocr = ocr;
                     }
                 }
                 Event::Move(x, y) => {
                     position = (x, y);
+                    if let Some(start) = start_point {
+                        windows
+                            .select(event_loop, &Lens::from(start, position))
+                            .log_error("绘制选区失败");
+                    }
                 }
                 Event::End => {
+                    windows.unselect();
                     if let Some(start) = start_point {
                         let lens = Lens::from(start, position);
-
-                        if let Lens {
-                            x,
-                            y,
-                            width: width @ MIN_WIDTH..,
-                            height: height @ MIN_HEIGHT..,
-                        } = lens
+                        if lens.width >= config.capture.min_width
+                            && lens.height >= config.capture.min_height
                         {
-                            screenshot(&lens)
-                                .and_then(|image| {
-                                    windows.create(
-                                        event_loop,
-                                        image,
-                                        PhysicalSize { width, height },
-                                        PhysicalPosition { x, y },
-                                    )
-                                })
-                                .log_error("截图失败");
+                            // 选区窗口刚隐藏时可能仍在屏幕上，稍后再截图
+                            let event_tx = capture_event_tx.clone();
+                            let ocr = capture_ocr.take();
+                            thread::spawn(move || {
+                                thread::sleep(OVERLAY_HIDE_DELAY);
+                                event_tx
+                                    .send_event(Event::Capture(lens, ocr))
+                                    .log_error("发送截图事件失败");
+                            });
                         }
                         start_point = None;
+                        capture_ocr = None;
+                    }
+                }
+                Event::Capture(lens, ocr) => {
+                    let Lens {
+                        x,
+                        y,
+                        width,
+                        height,
+                    } = lens;
+                    let window_id = screenshot(&lens, capture_event_tx.clone())
+                        .and_then(|(image, history)| {
+                            windows.create(
+                                event_loop,
+                                image,
+                                PhysicalSize { width, height },
+                                PhysicalPosition { x, y },
+                                &config.window,
+                                history,
+                            )
+                        })
+                        .log_error("截图失败");
+                    // 绑定指定了语言配置时截图后直接识别，没有贴图窗口时识别截图本身
+                    if let (Some(window_id), Some(profile)) = (window_id, ocr) {
+                        let request = Request::profile(profile);
+                        match window_id {
+                            Some(window_id) => windows.ocr(&window_id, request),
+                            None => windows.ocr_last(request),
+                        }
+                        .log_error("OCR失败");
                     }
                 }
+                Event::Cancel => {
+                    windows.unselect();
+                    start_point = None;
+                    capture_ocr = None;
+                }
                 Event::Pause => {
                     tray_icon
                         .set_tooltip(Some("中键截屏（关）"))
@@ -187,8 +336,46 @@ fn main() -> Result<()> {
                         .set_tooltip(Some("中键截屏"))
                         .log_error("变更TIP失败");
                 }
-                Event::Close(window_id) => {
-                    windows.destroy(&window_id);
+                Event::ConfigChanged => {
+                    config = config::current();
+                    menus.rebuild().log_error("重建菜单失败");
+                }
+                Event::ShowHistory => {
+                    windows
+                        .show_history(event_loop)
+                        .log_error("打开历史记录失败");
+                }
+                Event::HistoryChanged => {
+                    windows.history_changed().log_error("刷新历史记录失败");
+                }
+                Event::OcrLast(profile) => {
+                    let request = Request {
+                        profile,
+                        ..Default::default()
+                    };
+                    windows.ocr_last(request).log_error("OCR失败");
+                }
+                Event::Pin(window_id, action) => {
+                    windows
+                        .pin_action(window_id, action, &config.save)
+                        .log_error("贴图操作失败");
+                }
+                Event::ClickThroughOff => {
+                    windows.clear_click_through().log_error("取消鼠标穿透失败");
+                }
+                Event::Diagnose => diagnose::report(),
+                Event::OcrDone(window_id, job, result) => {
+                    windows
+                        .ocr_done(&window_id, job, result)
+                        .log_error("显示识别结果失败");
+                }
+                Event::OcrLastDone(job, result) => {
+                    windows.ocr_last_done(job, result).log_error("OCR失败");
+                }
+                Event::OcrFailed(window_id, job) => {
+                    windows
+                        .ocr_failed(&window_id, job)
+                        .log_error("停止识别动画失败");
                 }
                 Event::Redraw(window_id) => {
                     windows.redraw(window_id).log_error("重绘失败");
@@ -0,0 +1,188 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use tray_icon::menu::{Menu, MenuItem, PredefinedMenuItem, Submenu};
+
+use crate::{
+    config,
+    event::{Event, PinAction},
+    ocr,
+};
+
+const ZOOM_ITEMS: &[f32] = &[0.5, 1.0, 1.5, 2.0, 4.0];
+const OPACITY_ITEMS: &[f32] = &[1.0, 0.8, 0.6, 0.4, 0.2];
+
+/// 菜单项对应的事件，与托盘菜单线程共享
+pub type MenuItems = Arc<Mutex<Vec<(u32, Event)>>>;
+
+/// 随OCR配置变化的菜单：托盘中按语言识别上次截图的子菜单与贴图右键菜单，配置变化时重建
+pub struct Menus {
+    tray: Menu,
+    /// 子菜单在托盘菜单中的位置
+    position: usize,
+    ocr_last: Submenu,
+    pin: Menu,
+    /// 托盘固定菜单项对应的事件
+    fixed: Vec<(u32, Event)>,
+    items: MenuItems,
+}
+
+impl Menus {
+    /// 在托盘菜单的position处插入子菜单
+    pub fn new(tray: Menu, position: usize, fixed: Vec<(u32, Event)>) -> Result<Self> {
+        let (ocr_last, ocr_last_items) = ocr_last_menu()?;
+        tray.insert(&ocr_last, position)?;
+        let (pin, pin_items) = pin_menu()?;
+        let items = fixed
+            .iter()
+            .cloned()
+            .chain(ocr_last_items)
+            .chain(pin_items)
+            .collect();
+        Ok(Self {
+            tray,
+            position,
+            ocr_last,
+            pin,
+            fixed,
+            items: Arc::new(Mutex::new(items)),
+        })
+    }
+
+    /// 按当前配置重建，语言配置、预处理方案及模型是否齐全都可能变化
+    pub fn rebuild(&mut self) -> Result<()> {
+        let (ocr_last, ocr_last_items) = ocr_last_menu()?;
+        let (pin, pin_items) = pin_menu()?;
+        self.tray.remove(&self.ocr_last)?;
+        self.tray.insert(&ocr_last, self.position)?;
+        self.ocr_last = ocr_last;
+        self.pin = pin;
+        *self
+            .items
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = self
+            .fixed
+            .iter()
+            .cloned()
+            .chain(ocr_last_items)
+            .chain(pin_items)
+            .collect();
+        Ok(())
+    }
+
+    pub fn pin(&self) -> &Menu {
+        &self.pin
+    }
+
+    pub fn items(&self) -> MenuItems {
+        self.items.clone()
+    }
+}
+
+/// 贴图右键菜单，所有贴图共用，返回菜单及菜单项对应的事件
+fn pin_menu() -> Result<(Menu, Vec<(u32, Event)>)> {
+    let menu = Menu::new();
+    let mut items = Vec::new();
+    let ocr_with = profile_menu("按语言识别", &mut items, |name| {
+        Event::Pin(None, PinAction::OcrWith(name))
+    })?;
+    let ocr_preset = preset_menu(&mut items)?;
+    let mut item = |text: &str, action: PinAction| {
+        let item = MenuItem::new(text, true, None);
+        items.push((item.id(), Event::Pin(None, action)));
+        item
+    };
+
+    let ocr = item("识别文字", PinAction::Ocr);
+    let copy = item("复制图片", PinAction::CopyImage);
+    let copy_text = item("复制识别文字", PinAction::CopyText);
+    let save = item("保存", PinAction::Save);
+    let save_as = item("另存为…", PinAction::SaveAs);
+    let zoom = Submenu::new("缩放", true);
+    for &value in ZOOM_ITEMS {
+        zoom.append(&item(
+            &format!("{:.0}%", value * 100.0),
+            PinAction::Zoom(value),
+        ))?;
+    }
+    let opacity = Submenu::new("不透明度", true);
+    for &value in OPACITY_ITEMS {
+        opacity.append(&item(
+            &format!("{:.0}%", value * 100.0),
+            PinAction::Opacity(value),
+        ))?;
+    }
+    let click_through = item("鼠标穿透", PinAction::ClickThrough);
+    let close = item("关闭", PinAction::Close);
+    let close_all = item("关闭所有贴图", PinAction::CloseAll);
+
+    menu.append_items(&[
+        &ocr,
+        &ocr_with,
+        &ocr_preset,
+        &PredefinedMenuItem::separator(),
+        &copy,
+        &copy_text,
+        &save,
+        &save_as,
+        &PredefinedMenuItem::separator(),
+        &zoom,
+        &opacity,
+        &click_through,
+        &PredefinedMenuItem::separator(),
+        &close,
+        &close_all,
+    ])?;
+    Ok((menu, items))
+}
+
+/// 托盘菜单中按语言识别上次截图的子菜单
+fn ocr_last_menu() -> Result<(Submenu, Vec<(u32, Event)>)> {
+    let mut items = Vec::new();
+    let menu = profile_menu("按语言识别上次截图", &mut items, |name| {
+        Event::OcrLast(Some(name))
+    })?;
+    Ok((menu, items))
+}
+
+/// 按预处理方案识别的子菜单，默认方案标注“默认”
+fn preset_menu(items: &mut Vec<(u32, Event)>) -> Result<Submenu> {
+    let menu = Submenu::new("按预处理识别", true);
+    let config = config::current();
+    for preset in &config.ocr.presets {
+        let text = if preset.name == config.ocr.preprocess {
+            format!("{}（默认）", preset.name)
+        } else {
+            preset.name.clone()
+        };
+        let item = MenuItem::new(text, true, None);
+        items.push((
+            item.id(),
+            Event::Pin(None, PinAction::OcrPreset(preset.name.clone())),
+        ));
+        menu.append(&item)?;
+    }
+    Ok(menu)
+}
+
+/// 每个语言配置一项，默认配置标注“默认”，缺少模型的配置不可选
+fn profile_menu(
+    text: &str,
+    items: &mut Vec<(u32, Event)>,
+    event: impl Fn(String) -> Event,
+) -> Result<Submenu> {
+    let menu = Submenu::new(text, true);
+    let config = config::current();
+    for profile in &config.ocr.profiles {
+        let available = ocr::resolve(Some(&profile.name)).is_ok();
+        let text = if profile.name == config.ocr.default_profile {
+            format!("{}（默认）", profile.name)
+        } else {
+            profile.name.clone()
+        };
+        let item = MenuItem::new(text, available, None);
+        items.push((item.id(), event(profile.name.clone())));
+        menu.append(&item)?;
+    }
+    Ok(menu)
+}
@@ -0,0 +1,584 @@
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs, mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use lazy_static::lazy_static;
+use log_error::LogError;
+use tracing::{error, info, warn};
+
+use crate::{config, output::OcrFormat, preprocess, tesseract::Tesseract};
+
+/// tsv输出中词一级的level
+const WORD_LEVEL: u32 = 5;
+/// 同时保留的已初始化引擎数，按语言组合区分
+const MAX_ENGINES: usize = 3;
+/// 自动分割页面，不检测方向
+const DEFAULT_PSM: u32 = 3;
+/// 按模型可用的引擎
+const DEFAULT_OEM: u32 = 3;
+
+lazy_static! {
+    static ref WORKER: Mutex<Sender<Job>> = Mutex::new(spawn_worker());
+}
+
+enum Job {
+    Recognize {
+        tiff: Vec<u8>,
+        profile: Option<String>,
+        format: OcrFormat,
+        cancel: Cancel,
+        reply: Sender<Result<Recognition>>,
+    },
+    /// 预先加载模型，首次识别不再等待
+    WarmUp { profile: Option<String> },
+}
+
+/// 已初始化的引擎，加载时确定的设置都相同时复用
+struct Engine {
+    key: EngineKey,
+    tesseract: Tesseract,
+    used: Instant,
+}
+
+/// 引擎加载时确定的设置
+#[derive(Debug, Clone, PartialEq)]
+struct EngineKey {
+    tessdata_dir: PathBuf,
+    lang: String,
+    oem: u32,
+    /// 按参数名排序
+    variables: Vec<(String, String)>,
+}
+
+/// 语言配置对应的识别设置
+struct Settings {
+    key: EngineKey,
+    psm: u32,
+    dpi: Option<i32>,
+}
+
+impl Settings {
+    /// 未指定语言配置时使用默认配置，默认配置为空时使用所有语言
+    fn new(profile: Option<&str>) -> Result<Self> {
+        let (tessdata_dir, lang) = resolve(profile)?;
+        let config = config::current();
+        let name = profile.unwrap_or(&config.ocr.default_profile);
+        let found = config.ocr.profile(name);
+
+        let mut variables = BTreeMap::new();
+        variables.insert("preserve_interword_spaces".to_string(), "1".to_string());
+        if let Some(found) = found {
+            if let Some(whitelist) = &found.whitelist {
+                variables.insert("tessedit_char_whitelist".to_string(), whitelist.clone());
+            }
+            if let Some(blacklist) = &found.blacklist {
+                variables.insert("tessedit_char_blacklist".to_string(), blacklist.clone());
+            }
+            variables.extend(found.variables.clone());
+        }
+        Ok(Self {
+            key: EngineKey {
+                tessdata_dir,
+                lang,
+                oem: found.and_then(|found| found.oem).unwrap_or(DEFAULT_OEM),
+                variables: variables.into_iter().collect(),
+            },
+            psm: found.and_then(|found| found.psm).unwrap_or(DEFAULT_PSM),
+            dpi: found.and_then(|found| found.dpi),
+        })
+    }
+}
+
+/// 一次识别的选项，未指定的使用默认配置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Request {
+    /// 语言配置名
+    pub profile: Option<String>,
+    /// 预处理方案名
+    pub preset: Option<String>,
+}
+
+impl Request {
+    pub fn profile(profile: String) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Default::default()
+        }
+    }
+
+    pub fn preset(preset: String) -> Self {
+        Self {
+            preset: Some(preset),
+            ..Default::default()
+        }
+    }
+
+    /// 预处理方案：指定的方案，其次语言配置中的方案，最后是默认方案
+    fn preset_name(&self) -> String {
+        let config = config::current();
+        if let Some(preset) = &self.preset {
+            return preset.clone();
+        }
+        let profile = self
+            .profile
+            .as_deref()
+            .unwrap_or(&config.ocr.default_profile);
+        config
+            .ocr
+            .profile(profile)
+            .and_then(|profile| profile.preprocess.clone())
+            .unwrap_or_else(|| config.ocr.preprocess.clone())
+    }
+}
+
+/// 取消识别的标记，可在其它线程设置
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// 识别已取消
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OCR已取消")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// 识别到的一个词，坐标为图片像素
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub text: String,
+    /// 置信度，0～100
+    pub conf: f32,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    /// 所在的块、段落、行，同一行的词三者都相同
+    pub line: (u32, u32, u32),
+}
+
+impl Word {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.left as f64
+            && x < (self.left + self.width) as f64
+            && y >= self.top as f64
+            && y < (self.top + self.height) as f64
+    }
+
+    /// 点到词边框的距离，在词内时为0
+    pub fn distance(&self, x: f64, y: f64) -> f64 {
+        let dx = (self.left as f64 - x).max(x - (self.left + self.width) as f64);
+        let dy = (self.top as f64 - y).max(y - (self.top + self.height) as f64);
+        dx.max(0.0).hypot(dy.max(0.0))
+    }
+}
+
+/// 一次识别的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recognition {
+    pub text: String,
+    /// 按阅读顺序排列
+    pub words: Vec<Word>,
+    /// tesseract生成的hOCR、TSV或ALTO，坐标为预处理后的图片像素
+    pub document: Option<String>,
+}
+
+/// 预训练模型目录下所有语言
+pub fn languages(tessdata_dir: &Path) -> Result<Vec<String>> {
+    let train_files: Vec<String> = fs::read_dir(tessdata_dir)?
+        .filter_map(|f| {
+            if let Ok(f) = f {
+                let file_name = f.file_name();
+                let file_name = file_name.to_string_lossy();
+                if file_name.ends_with(".traineddata") {
+                    let file_name = file_name.trim_end_matches(".traineddata").to_string();
+                    return Some(file_name);
+                }
+                None
+            } else {
+                None
+            }
+        })
+        .collect();
+    if train_files.is_empty() {
+        return Err(anyhow!(
+            "请下载拷贝tesseract预训练模型至{}",
+            tessdata_dir.display()
+        ));
+    }
+    Ok(train_files)
+}
+
+/// 查找预训练模型的目录：配置的目录、TESSDATA_PREFIX（及其下的tessdata）、当前目录，按顺序去重
+pub fn tessdata_dirs() -> Vec<PathBuf> {
+    let mut candidates = vec![config::current().ocr.tessdata_dir.clone()];
+    if let Some(prefix) = env::var_os("TESSDATA_PREFIX") {
+        let prefix = PathBuf::from(prefix);
+        candidates.push(prefix.join("tessdata"));
+        candidates.push(prefix);
+    }
+    candidates.push(PathBuf::from("."));
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for dir in candidates {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        if dir.is_dir() && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// 语言配置对应的模型目录与语言组合，profile为None时使用默认配置。
+/// 一个引擎只能从一个目录加载模型，取第一个包含该配置所有语言的目录
+pub fn resolve(profile: Option<&str>) -> Result<(PathBuf, String)> {
+    let config = config::current();
+    let name = profile.unwrap_or(&config.ocr.default_profile);
+    if name.is_empty() {
+        return all_languages();
+    }
+    let Some(found) = config.ocr.profile(name) else {
+        return Err(anyhow!("未配置OCR语言{name}"));
+    };
+    let dirs = tessdata_dirs();
+    for dir in &dirs {
+        let installed = languages(dir).unwrap_or_default();
+        if found.languages.iter().all(|lang| installed.contains(lang)) {
+            return Ok((dir.clone(), found.languages.join("+")));
+        }
+    }
+    let err_msg = format!(
+        "找不到OCR语言{name}所需的模型{}，请下载拷贝至{}",
+        found.languages.join("、"),
+        config.ocr.tessdata_dir.display()
+    );
+    if profile.is_none() {
+        // 默认配置的模型不全时退回到所有语言，避免无法识别
+        warn!("{err_msg}，改用所有语言");
+        return all_languages();
+    }
+    Err(anyhow!(err_msg))
+}
+
+/// 第一个有模型的目录下的所有语言
+fn all_languages() -> Result<(PathBuf, String)> {
+    let dirs = tessdata_dirs();
+    for dir in &dirs {
+        if let Ok(langs) = languages(dir) {
+            return Ok((dir.clone(), langs.join("+")));
+        }
+    }
+    let tessdata_dir = config::current().ocr.tessdata_dir.clone();
+    Err(anyhow!(
+        "请下载拷贝tesseract预训练模型至{}",
+        tessdata_dir.display()
+    ))
+}
+
+/// 预处理后识别文字及每个词的位置，词的位置映射回原图；由常驻线程执行，复用已加载模型的引擎。
+/// 取消时返回Cancelled错误
+pub fn recognize(
+    image: RgbaImage,
+    request: &Request,
+    format: OcrFormat,
+    cancel: &Cancel,
+) -> Result<Recognition> {
+    let mut prepared = preprocess::prepare(image, &request.preset_name())?;
+    let (reply, result) = mpsc::channel();
+    send(Job::Recognize {
+        tiff: mem::take(&mut prepared.tiff),
+        profile: request.profile.clone(),
+        format,
+        cancel: cancel.clone(),
+        reply,
+    })?;
+    let mut recognition = result.recv().map_err(|_| anyhow!("OCR线程已退出"))??;
+    prepared.map_words(&mut recognition.words);
+    Ok(recognition)
+}
+
+/// 后台预先加载默认语言配置的模型
+pub fn warm_up() {
+    send(Job::WarmUp { profile: None }).log_error("预加载OCR模型失败");
+}
+
+fn send(job: Job) -> Result<()> {
+    let mut worker = WORKER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Err(mpsc::SendError(job)) = worker.send(job) {
+        // 线程意外退出时重新启动
+        error!("OCR线程已退出，重新启动");
+        *worker = spawn_worker();
+        worker.send(job).map_err(|_| anyhow!("OCR线程启动失败"))?;
+    }
+    Ok(())
+}
+
+fn spawn_worker() -> Sender<Job> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || work(receiver));
+    sender
+}
+
+fn work(jobs: Receiver<Job>) {
+    let mut engines: Vec<Engine> = Vec::new();
+    for job in jobs {
+        match job {
+            Job::Recognize {
+                tiff,
+                profile,
+                format,
+                cancel,
+                reply,
+            } => {
+                // 排队期间已取消的不再识别
+                let result = if cancel.is_cancelled() {
+                    Err(Cancelled.into())
+                } else {
+                    Settings::new(profile.as_deref()).and_then(|settings| {
+                        let tesseract = engine(&mut engines, &settings.key)?;
+                        run(tesseract, &settings, &tiff, format, &cancel)
+                    })
+                };
+                // 调用方已放弃等待时忽略
+                let _ = reply.send(result);
+            }
+            Job::WarmUp { profile } => {
+                Settings::new(profile.as_deref())
+                    .and_then(|settings| engine(&mut engines, &settings.key).map(|_| ()))
+                    .log_error("预加载OCR模型失败");
+            }
+        }
+    }
+}
+
+/// 取出设置相同的引擎，没有时初始化，超出数量时丢弃最久未用的
+fn engine<'a>(engines: &'a mut Vec<Engine>, key: &EngineKey) -> Result<&'a mut Tesseract> {
+    let index = match engines.iter().position(|engine| engine.key == *key) {
+        Some(index) => {
+            info!("复用已加载的OCR模型{}", key.lang);
+            index
+        }
+        None => {
+            let start = Instant::now();
+            let mut tesseract = Tesseract::new(&key.tessdata_dir, &key.lang, key.oem)?;
+            for (name, value) in &key.variables {
+                tesseract.set_variable(name, value)?;
+            }
+            info!("加载OCR模型{}耗时{:?}", key.lang, start.elapsed());
+            if engines.len() >= MAX_ENGINES {
+                if let Some(oldest) = engines
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, engine)| engine.used)
+                    .map(|(index, _)| index)
+                {
+                    engines.swap_remove(oldest);
+                }
+            }
+            engines.push(Engine {
+                key: key.clone(),
+                tesseract,
+                used: Instant::now(),
+            });
+            engines.len() - 1
+        }
+    };
+    let engine = &mut engines[index];
+    engine.used = Instant::now();
+    Ok(&mut engine.tesseract)
+}
+
+fn run(
+    tesseract: &mut Tesseract,
+    settings: &Settings,
+    tiff: &[u8],
+    format: OcrFormat,
+    cancel: &Cancel,
+) -> Result<Recognition> {
+    let config = config::current();
+    let start = Instant::now();
+    tesseract.set_image(tiff, config.ocr.dpi)?;
+    if let Some(dpi) = settings.dpi {
+        tesseract.set_source_resolution(dpi);
+    }
+    tesseract.set_page_seg_mode(settings.psm);
+    if !tesseract.recognize(&cancel.0)? {
+        info!("OCR在{:?}后取消", start.elapsed());
+        return Err(Cancelled.into());
+    }
+    // 以下复用上面的识别结果，不会重新识别
+    let text = tesseract.text()?;
+    let tsv = tesseract.tsv()?;
+    let words = parse_tsv(&tsv);
+    let document = match format {
+        OcrFormat::Hocr => Some(tesseract.hocr()?),
+        OcrFormat::Alto => Some(tesseract.alto()?),
+        OcrFormat::Tsv => Some(tsv),
+        OcrFormat::Text | OcrFormat::Json => None,
+    };
+    info!("OCR识别耗时{:?}", start.elapsed());
+    Ok(Recognition {
+        text,
+        words,
+        document,
+    })
+}
+
+/// 解析tesseract的tsv输出，只保留非空的词
+fn parse_tsv(tsv: &str) -> Vec<Word> {
+    tsv.lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.splitn(12, '\t').collect();
+            let [level, _page, block, par, line, _word, left, top, width, height, conf, text] =
+                columns.as_slice()
+            else {
+                return None;
+            };
+            if level.parse::<u32>().ok()? != WORD_LEVEL || text.trim().is_empty() {
+                return None;
+            }
+            Some(Word {
+                text: text.trim().to_string(),
+                conf: conf.parse().ok()?,
+                left: left.parse().ok()?,
+                top: top.parse().ok()?,
+                width: width.parse().ok()?,
+                height: height.parse().ok()?,
+                line: (block.parse().ok()?, par.parse().ok()?, line.parse().ok()?),
+            })
+        })
+        .collect()
+}
+
+/// 拼接词为文本，同一行以空格分隔（中日韩文字之间不加空格），不同行换行
+pub fn join(words: &[Word]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&Word> = None;
+    for word in words {
+        if let Some(previous) = previous {
+            if previous.line != word.line {
+                text.push('\n');
+            } else {
+                let end = previous.text.chars().last().unwrap_or(' ');
+                let start = word.text.chars().next().unwrap_or(' ');
+                if end.is_ascii() || start.is_ascii() {
+                    text.push(' ');
+                }
+            }
+        }
+        text.push_str(&word.text);
+        previous = Some(word);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, line: u32) -> Word {
+        Word {
+            text: text.to_string(),
+            conf: 90.0,
+            left: 0,
+            top: 0,
+            width: 10,
+            height: 10,
+            line: (1, 1, line),
+        }
+    }
+
+    #[test]
+    fn parse_tsv_keeps_only_words() {
+        let tsv = [
+            "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext",
+            "1\t1\t0\t0\t0\t0\t0\t0\t200\t100\t-1\t",
+            "2\t1\t1\t0\t0\t0\t10\t10\t180\t40\t-1\t",
+            "3\t1\t1\t1\t0\t0\t10\t10\t180\t40\t-1\t",
+            "4\t1\t1\t1\t1\t0\t10\t10\t180\t20\t-1\t",
+            "5\t1\t1\t1\t1\t1\t10\t10\t50\t20\t96.5\tHello",
+            "5\t1\t1\t1\t1\t2\t70\t10\t20\t20\t95\t ",
+            "5\t1\t1\t1\t1\t3\t100\t10\t60\t20\t42.25\ta\tb",
+            "5\t1\t1\t1\t2\t1\t10\t30\t40\t20\t88\t世界",
+        ]
+        .join("\n");
+        let words = parse_tsv(&tsv);
+        assert_eq!(words.len(), 3);
+        assert_eq!(
+            words[0],
+            Word {
+                text: "Hello".to_string(),
+                conf: 96.5,
+                left: 10,
+                top: 10,
+                width: 50,
+                height: 20,
+                line: (1, 1, 1),
+            }
+        );
+        // 文字中的制表符保留在最后一列
+        assert_eq!(words[1].text, "a\tb");
+        assert_eq!(words[1].conf, 42.25);
+        assert_eq!(words[2].text, "世界");
+        assert_eq!(words[2].line, (1, 1, 2));
+    }
+
+    #[test]
+    fn parse_tsv_skips_malformed_rows() {
+        assert!(parse_tsv("").is_empty());
+        assert!(parse_tsv("5\t1\t1\t1\t1\t1\t10\t10\t50\t20\t96").is_empty());
+        assert!(parse_tsv("5\t1\t1\t1\t1\t1\tx\t10\t50\t20\t96\tword").is_empty());
+    }
+
+    #[test]
+    fn join_spaces_between_ascii_words() {
+        assert_eq!(join(&[word("Hello", 1), word("world", 1)]), "Hello world");
+        assert_eq!(join(&[word("中文", 1), word("text", 1)]), "中文 text");
+        assert_eq!(join(&[word("text", 1), word("中文", 1)]), "text 中文");
+    }
+
+    #[test]
+    fn join_cjk_without_spaces() {
+        assert_eq!(join(&[word("你好", 1), word("世界", 1)]), "你好世界");
+    }
+
+    #[test]
+    fn join_lines() {
+        let words = [
+            word("a", 1),
+            word("b", 1),
+            word("中", 2),
+            word("文", 2),
+            word("c", 3),
+        ];
+        assert_eq!(join(&words), "a b\n中文\nc");
+        assert_eq!(join(&[]), "");
+    }
+}
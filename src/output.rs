@@ -0,0 +1,293 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+use tracing::info;
+
+use crate::{
+    config, history,
+    lens::Lens,
+    ocr::{self, Recognition, Word},
+    util,
+};
+
+/// 识别结果的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OcrFormat {
+    Text,
+    Hocr,
+    Tsv,
+    Alto,
+    /// 按行分组的词、屏幕坐标与置信度
+    Json,
+}
+
+/// 识别结果写入剪切板、保存到截图旁或两者
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OcrOutput {
+    Clipboard,
+    File,
+    Both,
+}
+
+impl OcrFormat {
+    pub const ALL: [OcrFormat; 5] = [
+        OcrFormat::Text,
+        OcrFormat::Hocr,
+        OcrFormat::Tsv,
+        OcrFormat::Alto,
+        OcrFormat::Json,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OcrFormat::Text => "txt",
+            OcrFormat::Hocr => "hocr",
+            OcrFormat::Tsv => "tsv",
+            OcrFormat::Alto => "xml",
+            OcrFormat::Json => "json",
+        }
+    }
+}
+
+/// 识别完成待输出的结果，附带发起时的格式、截图在屏幕上的位置与历史记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrResult {
+    pub recognition: Recognition,
+    pub format: OcrFormat,
+    pub lens: Lens,
+    pub history: Option<u64>,
+}
+
+/// 按格式输出识别结果，history为截图对应的历史记录，文件保存在历史截图旁，没有时保存到截图目录
+pub fn deliver(
+    recognition: &Recognition,
+    format: OcrFormat,
+    lens: &Lens,
+    history: Option<u64>,
+) -> Result<()> {
+    let content = render(recognition, format, lens)?;
+    let output = config::current().ocr.output;
+    if matches!(output, OcrOutput::File | OcrOutput::Both) {
+        let path = path(format, history)?;
+        fs::write(&path, &content).with_context(|| format!("写入OCR结果{}失败", path.display()))?;
+        info!("OCR结果已保存至{}", path.display());
+    }
+    if matches!(output, OcrOutput::Clipboard | OcrOutput::Both) {
+        util::copy_text(content)?;
+    }
+    Ok(())
+}
+
+/// 生成指定格式的文本，JSON中的坐标按lens换算为屏幕坐标
+pub fn render(recognition: &Recognition, format: OcrFormat, lens: &Lens) -> Result<String> {
+    Ok(match format {
+        OcrFormat::Text => recognition.text.clone(),
+        OcrFormat::Json => json(recognition, lens)?,
+        OcrFormat::Hocr | OcrFormat::Tsv | OcrFormat::Alto => {
+            recognition.document.clone().unwrap_or_default()
+        }
+    })
+}
+
+fn path(format: OcrFormat, history: Option<u64>) -> Result<PathBuf> {
+    if let Some(path) = history.and_then(|id| history::sidecar(id, format.extension())) {
+        return Ok(path);
+    }
+    let dir = config::current().save.dir.clone();
+    fs::create_dir_all(&dir).with_context(|| format!("创建截图目录{}失败", dir.display()))?;
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let stamp = now.format(format_description!(
+        "[year][month][day]_[hour][minute][second]_[subsecond digits:3]"
+    ))?;
+    Ok(dir.join(format!("ocr_{stamp}.{}", format.extension())))
+}
+
+/// 屏幕物理像素的`[x, y, width, height]`
+type Bbox = (i32, i32, u32, u32);
+
+/// 自定义的JSON格式，按行分组的词，bbox为屏幕坐标，confidence为0～100
+#[derive(Serialize)]
+struct Document<'a> {
+    text: &'a str,
+    confidence: Option<f32>,
+    bbox: Bbox,
+    lines: Vec<Line<'a>>,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    text: String,
+    confidence: Option<f32>,
+    bbox: Bbox,
+    words: Vec<WordBox<'a>>,
+}
+
+#[derive(Serialize)]
+struct WordBox<'a> {
+    text: &'a str,
+    confidence: Option<f32>,
+    bbox: Bbox,
+}
+
+fn json(recognition: &Recognition, lens: &Lens) -> Result<String> {
+    // 词按阅读顺序排列，同一行的词相邻
+    let mut groups: Vec<&[Word]> = Vec::new();
+    let mut start = 0;
+    for i in 1..=recognition.words.len() {
+        if i == recognition.words.len()
+            || recognition.words[i].line != recognition.words[start].line
+        {
+            groups.push(&recognition.words[start..i]);
+            start = i;
+        }
+    }
+
+    let lines = groups
+        .into_iter()
+        .map(|words| Line {
+            text: ocr::join(words),
+            confidence: confidence(words),
+            bbox: bbox(words, lens),
+            words: words
+                .iter()
+                .map(|word| WordBox {
+                    text: &word.text,
+                    confidence: number(word.conf),
+                    bbox: bbox(std::slice::from_ref(word), lens),
+                })
+                .collect(),
+        })
+        .collect();
+    let document = Document {
+        text: &recognition.text,
+        confidence: confidence(&recognition.words),
+        bbox: (lens.x, lens.y, lens.width, lens.height),
+        lines,
+    };
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// 词的外接矩形，换算为屏幕坐标
+fn bbox(words: &[Word], lens: &Lens) -> Bbox {
+    let left = words.iter().map(|word| word.left).min().unwrap_or(0);
+    let top = words.iter().map(|word| word.top).min().unwrap_or(0);
+    let right = words
+        .iter()
+        .map(|word| word.left + word.width)
+        .max()
+        .unwrap_or(0);
+    let bottom = words
+        .iter()
+        .map(|word| word.top + word.height)
+        .max()
+        .unwrap_or(0);
+    (
+        lens.x + left as i32,
+        lens.y + top as i32,
+        right - left,
+        bottom - top,
+    )
+}
+
+/// 平均置信度，没有词时为null
+fn confidence(words: &[Word]) -> Option<f32> {
+    if words.is_empty() {
+        return None;
+    }
+    number(words.iter().map(|word| word.conf).sum::<f32>() / words.len() as f32)
+}
+
+/// 保留两位小数，非有限值为null
+fn number(value: f32) -> Option<f32> {
+    value.is_finite().then(|| (value * 100.0).round() / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn word(text: &str, conf: f32, left: u32, top: u32, line: u32) -> Word {
+        Word {
+            text: text.to_string(),
+            conf,
+            left,
+            top,
+            width: 10,
+            height: 8,
+            line: (1, 1, line),
+        }
+    }
+
+    fn render_json(words: Vec<Word>, lens: &Lens) -> Value {
+        let recognition = Recognition {
+            text: ocr::join(&words),
+            words,
+            document: None,
+        };
+        let content = render(&recognition, OcrFormat::Json, lens).unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn json_offsets_bbox_by_lens() {
+        let lens = Lens {
+            x: -1920,
+            y: 100,
+            width: 200,
+            height: 50,
+        };
+        let value = render_json(vec![word("hello", 90.0, 5, 6, 1)], &lens);
+        assert_eq!(value["bbox"], json!([-1920, 100, 200, 50]));
+        assert_eq!(value["lines"][0]["bbox"], json!([-1915, 106, 10, 8]));
+        assert_eq!(
+            value["lines"][0]["words"][0]["bbox"],
+            json!([-1915, 106, 10, 8])
+        );
+    }
+
+    #[test]
+    fn json_groups_words_by_line() {
+        let lens = Lens {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        };
+        let value = render_json(
+            vec![
+                word("a", 90.0, 0, 0, 1),
+                word("b", 70.0, 20, 0, 1),
+                word("c", 80.0, 0, 20, 2),
+            ],
+            &lens,
+        );
+        let lines = value["lines"].as_array().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["text"], "a b");
+        assert_eq!(lines[0]["confidence"], 80.0);
+        assert_eq!(lines[0]["bbox"], json!([0, 0, 30, 8]));
+        assert_eq!(lines[0]["words"].as_array().unwrap().len(), 2);
+        assert_eq!(lines[1]["text"], "c");
+        assert_eq!(value["text"], "a b\nc");
+    }
+
+    #[test]
+    fn json_without_words() {
+        let lens = Lens {
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+        };
+        let value = render_json(Vec::new(), &lens);
+        assert_eq!(value["lines"], json!([]));
+        assert_eq!(value["confidence"], Value::Null);
+        assert_eq!(value["text"], "");
+    }
+}
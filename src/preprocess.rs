@@ -0,0 +1,467 @@
+use std::{fmt, fs, io::Cursor, path::Path};
+
+use anyhow::{anyhow, Error, Result};
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, GrayImage, ImageOutputFormat, Luma, RgbaImage,
+};
+use imageproc::{
+    contrast::{adaptive_threshold, otsu_level, threshold},
+    filter::median_filter,
+    geometric_transformations::{rotate_about_center, Interpolation},
+};
+use log_error::LogError;
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+use tracing::info;
+
+use crate::{config, ocr::Word};
+
+/// 放大的最大倍数
+const MAX_UPSCALE: u32 = 4;
+/// 纠偏时估计角度的步长，度
+const DESKEW_STEP: f32 = 0.25;
+/// 估计倾斜角度时最多采样的文字像素数
+const DESKEW_SAMPLES: usize = 200_000;
+
+/// 预处理步骤，配置中写作"名称"或"名称:参数"，如"adaptive:11"
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Step {
+    /// 高度小于该值时按整数倍放大，最多4倍
+    Upscale(u32),
+    /// 背景较暗（深色界面）时反色为白底黑字
+    Invert,
+    /// 中值滤波去噪，参数为半径
+    Denoise(u32),
+    /// Otsu全局二值化
+    Otsu,
+    /// 自适应二值化，参数为块半径
+    Adaptive(u32),
+    /// 纠正不超过该角度（度）的倾斜
+    Deskew(f32),
+    /// 四周填充背景色边框，像素
+    Pad(u32),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Upscale(min_height) => write!(f, "upscale:{min_height}"),
+            Step::Invert => write!(f, "invert"),
+            Step::Denoise(radius) => write!(f, "denoise:{radius}"),
+            Step::Otsu => write!(f, "otsu"),
+            Step::Adaptive(radius) => write!(f, "adaptive:{radius}"),
+            Step::Deskew(max_angle) => write!(f, "deskew:{max_angle}"),
+            Step::Pad(border) => write!(f, "pad:{border}"),
+        }
+    }
+}
+
+impl From<Step> for String {
+    fn from(step: Step) -> Self {
+        step.to_string()
+    }
+}
+
+impl TryFrom<String> for Step {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let (name, param) = match value.split_once(':') {
+            Some((name, param)) => (name.trim(), Some(param.trim())),
+            None => (value.trim(), None),
+        };
+        let number = |default: u32| -> Result<u32> {
+            let value = param.map_or(Ok(default), str::parse)?;
+            if value == 0 {
+                return Err(anyhow!("预处理步骤{name}的参数必须大于0"));
+            }
+            Ok(value)
+        };
+        let step = match name {
+            "upscale" => Step::Upscale(number(100)?),
+            "invert" => Step::Invert,
+            "denoise" => Step::Denoise(number(1)?),
+            "otsu" => Step::Otsu,
+            "adaptive" => Step::Adaptive(number(11)?),
+            "deskew" => {
+                let max_angle: f32 = param.map_or(Ok(10.0), str::parse)?;
+                if !(0.0..=45.0).contains(&max_angle) {
+                    return Err(anyhow!("deskew的角度必须在0～45之间"));
+                }
+                Step::Deskew(max_angle)
+            }
+            "pad" => Step::Pad(number(10)?),
+            _ => return Err(anyhow!("未知预处理步骤：{name}")),
+        };
+        if param.is_some() && matches!(step, Step::Invert | Step::Otsu) {
+            return Err(anyhow!("预处理步骤{name}没有参数"));
+        }
+        Ok(step)
+    }
+}
+
+/// 预处理对坐标的变换，识别结果按相反顺序映射回原图
+#[derive(Debug, Clone, Copy)]
+enum Geometry {
+    Scale(f32),
+    Translate(f32, f32),
+    /// 绕中心顺时针旋转，弧度
+    Rotate {
+        theta: f32,
+        center: (f32, f32),
+    },
+}
+
+/// 预处理后待识别的图片
+pub struct Prepared {
+    pub tiff: Vec<u8>,
+    size: (u32, u32),
+    geometry: Vec<Geometry>,
+}
+
+impl Prepared {
+    /// 把识别到的词的位置映射回原图
+    pub fn map_words(&self, words: &mut [Word]) {
+        if self.geometry.is_empty() {
+            return;
+        }
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        for word in words {
+            let (x0, y0) = (word.left as f32, word.top as f32);
+            let (x1, y1) = (x0 + word.width as f32, y0 + word.height as f32);
+            let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].map(|(x, y)| self.unmap(x, y));
+            let left = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min);
+            let top = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min);
+            let right = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max);
+            let bottom = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max);
+            let (left, top) = (left.clamp(0.0, width), top.clamp(0.0, height));
+            let (right, bottom) = (right.clamp(left, width), bottom.clamp(top, height));
+            word.left = left.floor() as u32;
+            word.top = top.floor() as u32;
+            word.width = (right.ceil() - left.floor()) as u32;
+            word.height = (bottom.ceil() - top.floor()) as u32;
+        }
+    }
+
+    fn unmap(&self, mut x: f32, mut y: f32) -> (f32, f32) {
+        for geometry in self.geometry.iter().rev() {
+            match *geometry {
+                Geometry::Scale(factor) => (x, y) = (x / factor, y / factor),
+                Geometry::Translate(dx, dy) => (x, y) = (x - dx, y - dy),
+                Geometry::Rotate { theta, center } => {
+                    let (sin, cos) = (-theta).sin_cos();
+                    let (dx, dy) = (x - center.0, y - center.1);
+                    (x, y) = (
+                        center.0 + dx * cos - dy * sin,
+                        center.1 + dx * sin + dy * cos,
+                    );
+                }
+            }
+        }
+        (x, y)
+    }
+}
+
+/// 按预处理方案处理截图并编码为tiff，name为空时不做处理；配置了debug_dir时写出每一步的结果
+pub fn prepare(image: RgbaImage, name: &str) -> Result<Prepared> {
+    let config = config::current();
+    let steps = match name {
+        "" => Vec::new(),
+        name => config
+            .ocr
+            .preset(name)
+            .ok_or_else(|| anyhow!("未配置预处理方案{name}"))?
+            .steps
+            .clone(),
+    };
+    let size = image.dimensions();
+    let debug = config
+        .ocr
+        .debug_dir
+        .as_deref()
+        .map(|dir| Debug::new(dir, name));
+
+    let mut geometry = Vec::new();
+    let image = if steps.is_empty() {
+        DynamicImage::from(image)
+    } else {
+        let mut gray = DynamicImage::from(image).to_luma8();
+        if let Some(debug) = &debug {
+            debug.write(0, "source", &gray);
+        }
+        for (i, step) in steps.iter().enumerate() {
+            gray = apply(*step, gray, &mut geometry);
+            if let Some(debug) = &debug {
+                debug.write(i + 1, &step.to_string().replace(':', "_"), &gray);
+            }
+        }
+        DynamicImage::from(gray)
+    };
+
+    // On windows, leptonica will only read tiff formatted files from memory.
+    let mut tiff: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut tiff), ImageOutputFormat::Tiff)?;
+    Ok(Prepared {
+        tiff,
+        size,
+        geometry,
+    })
+}
+
+fn apply(step: Step, mut image: GrayImage, geometry: &mut Vec<Geometry>) -> GrayImage {
+    match step {
+        Step::Upscale(min_height) => {
+            let (width, height) = image.dimensions();
+            if height == 0 || height >= min_height {
+                return image;
+            }
+            let factor = min_height.div_ceil(height).min(MAX_UPSCALE);
+            geometry.push(Geometry::Scale(factor as f32));
+            imageops::resize(
+                &image,
+                width * factor,
+                height * factor,
+                FilterType::CatmullRom,
+            )
+        }
+        Step::Invert => {
+            if background(&image) < 128 {
+                imageops::invert(&mut image);
+            }
+            image
+        }
+        Step::Denoise(radius) => median_filter(&image, radius, radius),
+        Step::Otsu => threshold(&image, otsu_level(&image)),
+        Step::Adaptive(radius) => adaptive_threshold(&image, radius),
+        Step::Deskew(max_angle) => {
+            let theta = skew(&image, max_angle);
+            if theta.abs() < DESKEW_STEP.to_radians() / 2.0 {
+                return image;
+            }
+            let (width, height) = image.dimensions();
+            geometry.push(Geometry::Rotate {
+                theta,
+                center: (width as f32 / 2.0, height as f32 / 2.0),
+            });
+            let fill = Luma([background(&image)]);
+            rotate_about_center(&image, theta, Interpolation::Bilinear, fill)
+        }
+        Step::Pad(border) => {
+            let (width, height) = image.dimensions();
+            let fill = Luma([background(&image)]);
+            let mut padded = GrayImage::from_pixel(width + border * 2, height + border * 2, fill);
+            imageops::replace(&mut padded, &image, border as i64, border as i64);
+            geometry.push(Geometry::Translate(border as f32, border as f32));
+            padded
+        }
+    }
+}
+
+/// 背景灰度：按Otsu阈值分为两类，取像素较多一类的平均值
+fn background(image: &GrayImage) -> u8 {
+    let level = otsu_level(image);
+    let (mut dark, mut light) = ((0u64, 0u64), (0u64, 0u64));
+    for &Luma([value]) in image.pixels() {
+        let class = if value > level { &mut light } else { &mut dark };
+        class.0 += 1;
+        class.1 += value as u64;
+    }
+    let (count, sum) = if light.0 >= dark.0 { light } else { dark };
+    sum.checked_div(count).unwrap_or(255) as u8
+}
+
+/// 估计使文字行水平需要顺时针旋转的角度（弧度）：文字像素按各角度投影到行上，取行分布最集中的角度
+fn skew(image: &GrayImage, max_angle: f32) -> f32 {
+    let level = otsu_level(image);
+    let dark = image.pixels().filter(|p| p.0[0] <= level).count();
+    // 文字是像素较少的一类
+    let text_is_dark = dark * 2 <= image.len();
+    let text: Vec<(f32, f32)> = image
+        .enumerate_pixels()
+        .filter(|(_, _, p)| (p.0[0] <= level) == text_is_dark)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    if text.is_empty() {
+        return 0.0;
+    }
+    let stride = text.len().div_ceil(DESKEW_SAMPLES);
+    let (width, height) = image.dimensions();
+    let rows = (width + height) as usize * 2;
+
+    let steps = (max_angle / DESKEW_STEP) as i32;
+    let mut best = (0.0, 0u64);
+    let mut histogram = vec![0u64; rows];
+    for i in -steps..=steps {
+        let theta = (i as f32 * DESKEW_STEP).to_radians();
+        let (sin, cos) = theta.sin_cos();
+        histogram.fill(0);
+        for &(x, y) in text.iter().step_by(stride) {
+            let row = (x * sin + y * cos) as isize + (width + height) as isize;
+            if let Some(count) = usize::try_from(row)
+                .ok()
+                .and_then(|row| histogram.get_mut(row))
+            {
+                *count += 1;
+            }
+        }
+        let score = histogram.iter().map(|count| count * count).sum();
+        if score > best.1 {
+            best = (theta, score);
+        }
+    }
+    best.0
+}
+
+/// 调试输出：把每一步的结果写入debug_dir
+struct Debug<'a> {
+    dir: &'a Path,
+    prefix: String,
+}
+
+impl<'a> Debug<'a> {
+    fn new(dir: &'a Path, preset: &str) -> Self {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let stamp = now
+            .format(format_description!(
+                "[year][month][day]_[hour][minute][second]_[subsecond digits:3]"
+            ))
+            .unwrap_or_default();
+        Self {
+            dir,
+            prefix: format!("ocr_{stamp}_{preset}"),
+        }
+    }
+
+    fn write(&self, index: usize, step: &str, image: &GrayImage) {
+        let path = self.dir.join(format!("{}_{index}_{step}.png", self.prefix));
+        let written = fs::create_dir_all(self.dir)
+            .map_err(Error::from)
+            .and_then(|_| Ok(image.save(&path)?))
+            .log_error("写入OCR预处理调试图片失败");
+        if written.is_some() {
+            info!("OCR预处理调试图片：{}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn step(value: &str) -> Result<Step> {
+        Step::try_from(value.to_string())
+    }
+
+    fn word(left: u32, top: u32, width: u32, height: u32) -> Word {
+        Word {
+            text: "word".to_string(),
+            conf: 90.0,
+            left,
+            top,
+            width,
+            height,
+            line: (1, 1, 1),
+        }
+    }
+
+    fn prepared(size: (u32, u32), geometry: Vec<Geometry>) -> Prepared {
+        Prepared {
+            tiff: Vec::new(),
+            size,
+            geometry,
+        }
+    }
+
+    /// 浅色或深色背景上一小块相反颜色的文字
+    fn image(background: u8, text: u8) -> GrayImage {
+        GrayImage::from_fn(40, 20, |x, y| {
+            if (5..15).contains(&x) && (5..10).contains(&y) {
+                Luma([text])
+            } else {
+                Luma([background])
+            }
+        })
+    }
+
+    #[test]
+    fn step_defaults() {
+        assert_eq!(step("upscale").unwrap(), Step::Upscale(100));
+        assert_eq!(step("invert").unwrap(), Step::Invert);
+        assert_eq!(step("denoise").unwrap(), Step::Denoise(1));
+        assert_eq!(step("otsu").unwrap(), Step::Otsu);
+        assert_eq!(step("adaptive").unwrap(), Step::Adaptive(11));
+        assert_eq!(step("deskew").unwrap(), Step::Deskew(10.0));
+        assert_eq!(step("pad").unwrap(), Step::Pad(10));
+        assert_eq!(step(" adaptive : 7 ").unwrap(), Step::Adaptive(7));
+    }
+
+    #[test]
+    fn step_rejects_bad_params() {
+        assert!(step("adaptive:0").is_err());
+        assert!(step("upscale:0").is_err());
+        assert!(step("invert:1").is_err());
+        assert!(step("otsu:1").is_err());
+        assert!(step("deskew:46").is_err());
+        assert!(step("deskew:-1").is_err());
+        assert!(step("sharpen").is_err());
+    }
+
+    #[test]
+    fn step_round_trip() {
+        for value in ["upscale:120", "invert", "deskew:2.5", "pad:4"] {
+            assert_eq!(String::from(step(value).unwrap()), value);
+        }
+    }
+
+    #[test]
+    fn map_words_through_scale_and_translate() {
+        // 先放大2倍再四周填充10像素
+        let prepared = prepared(
+            (100, 50),
+            vec![Geometry::Scale(2.0), Geometry::Translate(10.0, 10.0)],
+        );
+        let mut words = [word(30, 20, 8, 4), word(0, 0, 300, 200)];
+        prepared.map_words(&mut words);
+        assert_eq!(
+            (words[0].left, words[0].top, words[0].width, words[0].height),
+            (10, 5, 4, 2)
+        );
+        // 超出原图的部分截掉
+        assert_eq!(
+            (words[1].left, words[1].top, words[1].width, words[1].height),
+            (0, 0, 100, 50)
+        );
+    }
+
+    #[test]
+    fn unmap_rotate() {
+        let prepared = prepared(
+            (100, 100),
+            vec![Geometry::Rotate {
+                theta: FRAC_PI_2,
+                center: (50.0, 50.0),
+            }],
+        );
+        let (x, y) = prepared.unmap(60.0, 50.0);
+        assert!((x - 50.0).abs() < 1e-3, "{x}");
+        assert!((y - 40.0).abs() < 1e-3, "{y}");
+    }
+
+    #[test]
+    fn map_words_without_geometry() {
+        let prepared = prepared((10, 10), Vec::new());
+        let mut words = [word(1, 2, 3, 4)];
+        prepared.map_words(&mut words);
+        assert_eq!(words[0], word(1, 2, 3, 4));
+    }
+
+    #[test]
+    fn background_of_light_and_dark_images() {
+        assert_eq!(background(&image(240, 10)), 240);
+        assert_eq!(background(&image(20, 250)), 20);
+    }
+}
@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Ok, Result};
+use tracing::warn;
+use wgpu::util::DeviceExt;
+use winit::{dpi::PhysicalSize, window::Window};
+
+use super::{texture, PinUniforms, ShapeVertex, Vertex, INDICES, VERTICES};
+
+/// 启动自检：不创建窗口查找可用的适配器，返回适配器名称
+pub fn probe() -> Result<String> {
+    let instance = instance();
+    let request_adapter = |force_fallback_adapter| {
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter,
+        }))
+    };
+    let adapter = request_adapter(false)
+        .or_else(|| request_adapter(true))
+        .ok_or_else(|| anyhow!("没有可用的图形适配器"))?;
+    let info = adapter.get_info();
+    Ok(format!("{}（{:?}）", info.name, info.backend))
+}
+
+fn instance() -> wgpu::Instance {
+    // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
+    let backends = if cfg!(windows) {
+        // Vulkan在win11上不稳定，会出现白窗
+        wgpu::Backends::DX12
+    } else {
+        wgpu::Backends::all()
+    };
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+}
+
+/// 所有窗口共享的渲染上下文：设备、管线与顶点缓冲只创建一次
+pub struct Context {
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    /// 首个窗口surface选定的格式，管线按该格式创建
+    format: wgpu::TextureFormat,
+    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub uniforms_bind_group_layout: wgpu::BindGroupLayout,
+    /// 贴图，不混合
+    pub render_pipeline: wgpu::RenderPipeline,
+    /// OCR进行中的动画
+    pub animation_pipeline: wgpu::RenderPipeline,
+    /// 带alpha混合的贴图，用于标签
+    pub blend_pipeline: wgpu::RenderPipeline,
+    /// 纯色图形
+    pub shape_pipeline: wgpu::RenderPipeline,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+    /// 整数倍缩放时按像素放大
+    nearest_sampler: wgpu::Sampler,
+    /// 不透明、不预乘的贴图参数，用于标签
+    pub opaque_bind_group: wgpu::BindGroup,
+}
+
+impl Context {
+    /// 以首个窗口选择适配器，同时返回该窗口的surface
+    pub async fn new(window: &Window) -> Result<(Self, wgpu::Surface)> {
+        // The instance is a handle to our GPU
+        let instance = instance();
+
+        // # Safety
+        //
+        // The surface needs to live as long as the window that created it.
+        // The window state that receives it owns the window.
+        let surface = unsafe { instance.create_surface(window) }?;
+
+        // 没有合适的硬件适配器时（虚拟机、远程桌面）退回软件适配器
+        let request_adapter = |force_fallback_adapter| {
+            instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: Some(&surface),
+                force_fallback_adapter,
+            })
+        };
+        let adapter = match request_adapter(false).await {
+            Some(adapter) => adapter,
+            None => {
+                warn!("没有可用的图形适配器，尝试软件适配器");
+                request_adapter(true)
+                    .await
+                    .ok_or_else(|| anyhow!("没有可用的图形适配器"))?
+            }
+        };
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    // WebGL doesn't support all of wgpu's features, so if
+                    // we're building for the web we'll have to disable some.
+                    limits: wgpu::Limits::default(),
+                },
+                None, // Trace path
+            )
+            .await?;
+
+        let surface_caps = surface.get_capabilities(&adapter);
+        // Shader code in this tutorial assumes an Srgb surface texture. Using a different
+        // one will result all the colors coming out darker. If you want to support non
+        // Srgb surfaces, you'll need to account for that when drawing to the frame.
+        let format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .or_else(|| surface_caps.formats.first().copied())
+            .ok_or_else(|| anyhow!("窗口不支持任何显示格式"))?;
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("texture_bind_group_layout"),
+            });
+
+        let uniforms_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    count: None,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                }],
+            });
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+        let animation_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Animation"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("animation.wgsl").into()),
+        });
+        let shape_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shape"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shape.wgsl").into()),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&texture_bind_group_layout, &uniforms_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let animation_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Animation Pipeline Layout"),
+                bind_group_layouts: &[&uniforms_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let shape_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shape Pipeline Layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+
+        let create_pipeline = |module, pipeline_layout, buffer, blend, cull_mode| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Render Pipeline"),
+                layout: Some(pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module,
+                    entry_point: "vs_main",
+                    buffers: &[buffer],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode,
+                    // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+                    // or Features::POLYGON_MODE_POINT
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    // Requires Features::DEPTH_CLIP_CONTROL
+                    unclipped_depth: false,
+                    // Requires Features::CONSERVATIVE_RASTERIZATION
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                // If the pipeline will be used with a multiview render pass, this
+                // indicates how many array layers the attachments will have.
+                multiview: None,
+            })
+        };
+
+        let replace = wgpu::BlendState {
+            color: wgpu::BlendComponent::REPLACE,
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let render_pipeline = create_pipeline(
+            &module,
+            &render_pipeline_layout,
+            Vertex::desc(),
+            replace,
+            Some(wgpu::Face::Back),
+        );
+        let animation_pipeline = create_pipeline(
+            &animation_module,
+            &animation_pipeline_layout,
+            Vertex::desc(),
+            replace,
+            Some(wgpu::Face::Back),
+        );
+        let blend_pipeline = create_pipeline(
+            &module,
+            &render_pipeline_layout,
+            Vertex::desc(),
+            wgpu::BlendState::ALPHA_BLENDING,
+            None,
+        );
+        let shape_pipeline = create_pipeline(
+            &shape_module,
+            &shape_pipeline_layout,
+            ShapeVertex::desc(),
+            wgpu::BlendState::ALPHA_BLENDING,
+            None,
+        );
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let opaque_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Opaque Buffer"),
+            contents: PinUniforms::default().as_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let opaque_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("opaque_bind_group"),
+            layout: &uniforms_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: opaque_buffer.as_entire_binding(),
+            }],
+        });
+
+        let context = Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            format,
+            texture_bind_group_layout,
+            uniforms_bind_group_layout,
+            render_pipeline,
+            animation_pipeline,
+            blend_pipeline,
+            shape_pipeline,
+            vertex_buffer,
+            index_buffer,
+            num_indices: INDICES.len() as u32,
+            nearest_sampler,
+            opaque_bind_group,
+        };
+        Ok((context, surface))
+    }
+
+    /// 为后续窗口创建surface
+    pub fn create_surface(&self, window: &Window) -> Result<wgpu::Surface> {
+        // # Safety
+        //
+        // The window state that receives the surface owns the window.
+        let surface = unsafe { self.instance.create_surface(window) }?;
+        if !self.adapter.is_surface_supported(&surface) {
+            return Err(anyhow!("图形适配器不支持该窗口"));
+        }
+        self.check_format(&surface.get_capabilities(&self.adapter))?;
+        Ok(surface)
+    }
+
+    /// 渲染管线按首个窗口选定的格式创建，其它窗口的surface须支持同一格式
+    fn check_format(&self, surface_caps: &wgpu::SurfaceCapabilities) -> Result<()> {
+        if surface_caps.formats.contains(&self.format) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "窗口不支持显示格式{:?}，支持{:?}",
+                self.format,
+                surface_caps.formats
+            ))
+        }
+    }
+
+    /// 按窗口尺寸配置surface，透明窗口优先选择尊重alpha通道的合成方式
+    pub fn configure(
+        &self,
+        surface: &wgpu::Surface,
+        size: PhysicalSize<u32>,
+        transparent: bool,
+    ) -> Result<wgpu::SurfaceConfiguration> {
+        let surface_caps = surface.get_capabilities(&self.adapter);
+        self.check_format(&surface_caps)?;
+        let alpha_mode = surface_caps
+            .alpha_modes
+            .iter()
+            .copied()
+            .find(|m| {
+                transparent
+                    && matches!(
+                        m,
+                        wgpu::CompositeAlphaMode::PostMultiplied
+                            | wgpu::CompositeAlphaMode::PreMultiplied
+                    )
+            })
+            .or_else(|| surface_caps.alpha_modes.first().copied())
+            .unwrap_or(wgpu::CompositeAlphaMode::Auto);
+        let present_mode = surface_caps
+            .present_modes
+            .first()
+            .copied()
+            .unwrap_or(wgpu::PresentMode::Fifo);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: self.format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode,
+            alpha_mode,
+            view_formats: vec![],
+        };
+        surface.configure(&self.device, &config);
+        Ok(config)
+    }
+
+    pub fn texture_bind_group(&self, texture: &texture::Texture) -> wgpu::BindGroup {
+        self.bind_group(texture, &texture.sampler)
+    }
+
+    /// 使用最近邻采样的绑定组
+    pub fn nearest_bind_group(&self, texture: &texture::Texture) -> wgpu::BindGroup {
+        self.bind_group(texture, &self.nearest_sampler)
+    }
+
+    fn bind_group(&self, texture: &texture::Texture, sampler: &wgpu::Sampler) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("diffuse_bind_group"),
+        })
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{hash_map, HashMap},
+    time::Instant,
+};
+
+use anyhow::Result;
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+use log_error::LogError;
+use screenshots::Image;
+use time::{macros::format_description, OffsetDateTime, UtcOffset};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    },
+    window::{Window, WindowId},
+};
+
+use super::{glyph, Renderer, State, Target};
+use crate::history::{self, Entry};
+
+const COLUMNS: u32 = 4;
+const ROWS: u32 = 3;
+const CELL_WIDTH: u32 = 200;
+const CELL_HEIGHT: u32 = 170;
+const PADDING: u32 = 8;
+const LABEL_HEIGHT: u32 = 16;
+const BORDER_WIDTH: u32 = 3;
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const CELL_BACKGROUND: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const BORDER_COLOR: Rgba<u8> = Rgba([25, 153, 255, 255]);
+/// 已有OCR结果的标记
+const TEXT_COLOR: Rgba<u8> = Rgba([80, 200, 120, 255]);
+const DOUBLE_CLICK_MILLIS: u128 = 400;
+
+/// 历史窗口中的操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Copy(u64),
+    Pin(u64),
+    Ocr(u64),
+    Delete(u64),
+    Close,
+}
+
+/// 历史记录窗口：缩略图网格，单击选中，双击或回车贴图，C复制，O识别，Delete删除
+pub struct Gallery {
+    state: State,
+    entries: Vec<Entry>,
+    thumbnails: HashMap<u64, RgbaImage>,
+    selected: usize,
+    /// 首个可见行
+    scroll: usize,
+    cursor: PhysicalPosition<f64>,
+    last_click: Option<(usize, Instant)>,
+}
+
+impl Gallery {
+    pub fn size() -> PhysicalSize<u32> {
+        PhysicalSize::new(COLUMNS * CELL_WIDTH, ROWS * CELL_HEIGHT)
+    }
+
+    pub fn new(renderer: &Renderer, window: Window, target: Target) -> Result<Self> {
+        let size = Self::size();
+        let blank = Image::new(
+            size.width,
+            size.height,
+            BACKGROUND.0.repeat((size.width * size.height) as usize),
+        );
+        let state = State::new(renderer, window, target, blank, size)?;
+        let mut gallery = Self {
+            state,
+            entries: Vec::new(),
+            thumbnails: HashMap::new(),
+            selected: 0,
+            scroll: 0,
+            cursor: PhysicalPosition::new(0.0, 0.0),
+            last_click: None,
+        };
+        gallery.refresh(renderer)?;
+        Ok(gallery)
+    }
+
+    pub fn get_id(&self) -> WindowId {
+        self.state.get_id()
+    }
+
+    pub fn focus(&self) {
+        self.state.visible();
+        self.state.window().focus_window();
+    }
+
+    /// 重新读取历史记录并重绘
+    pub fn refresh(&mut self, renderer: &Renderer) -> Result<()> {
+        self.entries = history::entries();
+        self.thumbnails
+            .retain(|id, _| self.entries.iter().any(|entry| entry.id == *id));
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        self.redraw(renderer)
+    }
+
+    pub fn render(&mut self, renderer: &Renderer) -> Result<()> {
+        self.state.render(renderer)
+    }
+
+    /// 处理窗口事件，返回需要执行的操作
+    pub fn input(&mut self, renderer: &Renderer, event: &WindowEvent) -> Result<Option<Action>> {
+        let selected = self.entries.get(self.selected).map(|entry| entry.id);
+        match *event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = position;
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let Some(index) = self.hit(self.cursor) else {
+                    return Ok(None);
+                };
+                let double = matches!(
+                    self.last_click,
+                    Some((last, at)) if last == index && at.elapsed().as_millis() < DOUBLE_CLICK_MILLIS
+                );
+                self.last_click = Some((index, Instant::now()));
+                self.select(renderer, index)?;
+                if double {
+                    return Ok(self.entries.get(index).map(|entry| Action::Pin(entry.id)));
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        (position.y / CELL_HEIGHT as f64) as f32
+                    }
+                };
+                if lines > 0.0 {
+                    self.scroll = self.scroll.saturating_sub(1);
+                } else if lines < 0.0 && self.scroll + 1 < self.rows() {
+                    self.scroll += 1;
+                }
+                self.redraw(renderer)?;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                let columns = COLUMNS as usize;
+                match key {
+                    VirtualKeyCode::Left => {
+                        self.select(renderer, self.selected.saturating_sub(1))?
+                    }
+                    VirtualKeyCode::Right => self.select(renderer, self.selected + 1)?,
+                    VirtualKeyCode::Up => {
+                        self.select(renderer, self.selected.saturating_sub(columns))?
+                    }
+                    VirtualKeyCode::Down => self.select(renderer, self.selected + columns)?,
+                    VirtualKeyCode::Return => return Ok(selected.map(Action::Pin)),
+                    VirtualKeyCode::C => return Ok(selected.map(Action::Copy)),
+                    VirtualKeyCode::O => return Ok(selected.map(Action::Ocr)),
+                    VirtualKeyCode::Delete => return Ok(selected.map(Action::Delete)),
+                    VirtualKeyCode::Escape => return Ok(Some(Action::Close)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn rows(&self) -> usize {
+        self.entries.len().div_ceil(COLUMNS as usize)
+    }
+
+    /// 窗口坐标对应的记录序号
+    fn hit(&self, position: PhysicalPosition<f64>) -> Option<usize> {
+        if position.x < 0.0 || position.y < 0.0 {
+            return None;
+        }
+        let column = position.x as u32 / CELL_WIDTH;
+        let row = position.y as u32 / CELL_HEIGHT;
+        if column >= COLUMNS || row >= ROWS {
+            return None;
+        }
+        let index = (self.scroll + row as usize) * COLUMNS as usize + column as usize;
+        (index < self.entries.len()).then_some(index)
+    }
+
+    /// 选中记录，超出可见范围时滚动
+    fn select(&mut self, renderer: &Renderer, index: usize) -> Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        self.selected = index.min(self.entries.len() - 1);
+        let row = self.selected / COLUMNS as usize;
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + ROWS as usize {
+            self.scroll = row + 1 - ROWS as usize;
+        }
+        self.redraw(renderer)
+    }
+
+    fn redraw(&mut self, renderer: &Renderer) -> Result<()> {
+        self.scroll = self.scroll.min(self.rows().saturating_sub(1));
+        let sheet = self.compose();
+        self.state.set_image(
+            renderer,
+            Image::new(sheet.width(), sheet.height(), sheet.into_raw()),
+        )?;
+        self.state.window().set_title(&format!(
+            "历史记录（{}条）双击/回车贴图 C复制 O识别 Delete删除",
+            self.entries.len()
+        ));
+        self.state.render(renderer)
+    }
+
+    /// 在CPU上拼出当前可见的缩略图网格
+    fn compose(&mut self) -> RgbaImage {
+        let size = Self::size();
+        let mut sheet: RgbaImage = ImageBuffer::from_pixel(size.width, size.height, BACKGROUND);
+        let first = self.scroll * COLUMNS as usize;
+        let last = (first + (COLUMNS * ROWS) as usize).min(self.entries.len());
+
+        for index in first..last {
+            let entry = self.entries[index].clone();
+            let cell = (index - first) as u32;
+            let left = cell % COLUMNS * CELL_WIDTH;
+            let top = cell / COLUMNS * CELL_HEIGHT;
+
+            let color = if index == self.selected {
+                BORDER_COLOR
+            } else {
+                CELL_BACKGROUND
+            };
+            fill(
+                &mut sheet,
+                (left + PADDING / 2, top + PADDING / 2),
+                (CELL_WIDTH - PADDING, CELL_HEIGHT - PADDING),
+                color,
+            );
+            fill(
+                &mut sheet,
+                (
+                    left + PADDING / 2 + BORDER_WIDTH,
+                    top + PADDING / 2 + BORDER_WIDTH,
+                ),
+                (
+                    CELL_WIDTH - PADDING - 2 * BORDER_WIDTH,
+                    CELL_HEIGHT - PADDING - 2 * BORDER_WIDTH,
+                ),
+                CELL_BACKGROUND,
+            );
+
+            let thumb_width = CELL_WIDTH - 2 * PADDING;
+            let thumb_height = CELL_HEIGHT - 2 * PADDING - LABEL_HEIGHT;
+            if let Some(thumbnail) = self.thumbnail(&entry, thumb_width, thumb_height) {
+                let x = left + PADDING + (thumb_width - thumbnail.width()) / 2;
+                let y = top + PADDING + (thumb_height - thumbnail.height()) / 2;
+                imageops::overlay(&mut sheet, thumbnail, x as i64, y as i64);
+            }
+
+            let label_top = top + CELL_HEIGHT - PADDING - LABEL_HEIGHT + 2;
+            if let Some(time) = format_time(entry.time) {
+                draw_label(&mut sheet, &time, left + PADDING, label_top);
+            }
+            let size = format!("{}×{}", entry.lens.width, entry.lens.height);
+            let (rgba, width, height) = glyph::label(&size, 1);
+            if let Some(label) = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, rgba) {
+                let x = (left + CELL_WIDTH - PADDING).saturating_sub(width);
+                imageops::overlay(&mut sheet, &label, x as i64, label_top as i64);
+            }
+            if entry.text.is_some() {
+                fill(
+                    &mut sheet,
+                    (left + CELL_WIDTH - PADDING - 10, top + PADDING),
+                    (8, 8),
+                    TEXT_COLOR,
+                );
+            }
+        }
+        sheet
+    }
+
+    fn thumbnail(&mut self, entry: &Entry, width: u32, height: u32) -> Option<&RgbaImage> {
+        if let hash_map::Entry::Vacant(slot) = self.thumbnails.entry(entry.id) {
+            let image = history::image(entry.id).log_error("读取历史截图失败")?;
+            let image: RgbaImage =
+                ImageBuffer::from_raw(image.width(), image.height(), image.rgba().to_vec())?;
+            let scale = (width as f32 / image.width() as f32)
+                .min(height as f32 / image.height() as f32)
+                .min(1.0);
+            let thumbnail = imageops::thumbnail(
+                &image,
+                ((image.width() as f32 * scale) as u32).max(1),
+                ((image.height() as f32 * scale) as u32).max(1),
+            );
+            slot.insert(thumbnail);
+        }
+        self.thumbnails.get(&entry.id)
+    }
+}
+
+fn fill(sheet: &mut RgbaImage, (x, y): (u32, u32), (width, height): (u32, u32), color: Rgba<u8>) {
+    for py in y..(y + height).min(sheet.height()) {
+        for px in x..(x + width).min(sheet.width()) {
+            sheet.put_pixel(px, py, color);
+        }
+    }
+}
+
+fn draw_label(sheet: &mut RgbaImage, text: &str, x: u32, y: u32) {
+    let (rgba, width, height) = glyph::label(text, 1);
+    if let Some(label) = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, rgba) {
+        imageops::overlay(sheet, &label, x as i64, y as i64);
+    }
+}
+
+/// 本地时间，点阵字模只有数字和-.，格式为10-17 08.30
+fn format_time(timestamp: i64) -> Option<String> {
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()?
+        .to_offset(offset)
+        .format(format_description!("[month]-[day] [hour].[minute]"))
+        .ok()
+}
@@ -0,0 +1,213 @@
+use std::fs;
+
+use anyhow::{anyhow, bail, Context, Result};
+use font_kit::{
+    family_name::FamilyName, handle::Handle, properties::Properties, source::SystemSource,
+};
+use lazy_static::lazy_static;
+use rusttype::{point, Font, PositionedGlyph, Scale};
+
+use crate::ocr::Word;
+
+/// 5x7点阵字模，仅覆盖尺寸、缩放比例等提示所需的字符
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const PADDING: u32 = 3;
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+const BACKGROUND: [u8; 4] = [0, 0, 0, 160];
+/// 词框内文字的底色，盖住原图中的文字
+const WORD_BACKGROUND: [u8; 4] = [0, 0, 0, 200];
+/// 点阵字模以外的字符（如识别到的中文）用系统字体绘制，每倍缩放的像素高度
+const FONT_SIZE: f32 = 9.0;
+/// 依次查找的系统中文字体，都没有时取系统默认无衬线字体
+const FONT_FAMILIES: &[&str] = &[
+    "Microsoft YaHei",
+    "SimHei",
+    "SimSun",
+    "PingFang SC",
+    "Noto Sans CJK SC",
+    "Source Han Sans SC",
+    "WenQuanYi Micro Hei",
+];
+
+lazy_static! {
+    static ref FONT: Result<(Font<'static>, String)> = load_font();
+}
+
+/// 查找能显示中文的系统字体，返回字体及名称
+fn load_font() -> Result<(Font<'static>, String)> {
+    let families: Vec<FamilyName> = FONT_FAMILIES
+        .iter()
+        .map(|family| FamilyName::Title(family.to_string()))
+        .chain([FamilyName::SansSerif])
+        .collect();
+    let handle = SystemSource::new()
+        .select_best_match(&families, &Properties::new())
+        .context("未找到系统字体")?;
+    let name = handle.load().context("加载系统字体失败")?.full_name();
+    let (data, index) = match handle {
+        Handle::Path { path, font_index } => (
+            fs::read(&path).with_context(|| format!("读取字体{}失败", path.display()))?,
+            font_index,
+        ),
+        Handle::Memory { bytes, font_index } => (bytes.to_vec(), font_index),
+    };
+    let font =
+        Font::try_from_vec_and_index(data, index).ok_or_else(|| anyhow!("无法解析字体{name}"))?;
+    if font.glyph('中').id().0 == 0 {
+        bail!("系统字体{name}不含中文字形");
+    }
+    Ok((font, name))
+}
+
+/// 绘制标签与词框文字所用的系统字体名称，未找到可用的中文字体时返回原因
+pub fn font_name() -> Result<&'static str> {
+    match FONT.as_ref() {
+        Ok((_, name)) => Ok(name),
+        Err(e) => Err(anyhow!("{e:#}")),
+    }
+}
+
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        '×' | 'x' => [0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        _ => [0x00; 7],
+    }
+}
+
+/// 渲染文字标签为rgba位图，返回（像素, 宽, 高）；有点阵字模以外的字符时用系统字体
+pub fn label(text: &str, scale: u32) -> (Vec<u8>, u32, u32) {
+    let scale = scale.max(1);
+    if text.chars().any(|c| c != ' ' && glyph(c) == [0x00; 7]) {
+        if let Ok((font, _)) = FONT.as_ref() {
+            return font_label(font, text, scale);
+        }
+    }
+    let count = text.chars().count() as u32;
+    let width = (count * (GLYPH_WIDTH + 1) + 2 * PADDING - 1) * scale;
+    let height = (GLYPH_HEIGHT + 2 * PADDING) * scale;
+
+    let mut rgba = BACKGROUND.repeat((width * height) as usize);
+    for (i, c) in text.chars().enumerate() {
+        let rows = glyph(c);
+        let left = PADDING + i as u32 * (GLYPH_WIDTH + 1);
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = (left + col) * scale + dx;
+                        let y = (PADDING + row as u32) * scale + dy;
+                        let offset = ((y * width + x) * 4) as usize;
+                        rgba[offset..offset + 4].copy_from_slice(&FOREGROUND);
+                    }
+                }
+            }
+        }
+    }
+
+    (rgba, width, height)
+}
+
+fn font_label(font: &Font, text: &str, scale: u32) -> (Vec<u8>, u32, u32) {
+    let size = Scale::uniform(FONT_SIZE * scale as f32);
+    let padding = PADDING * scale;
+    let v_metrics = font.v_metrics(size);
+    let glyphs: Vec<_> = font
+        .layout(
+            text,
+            size,
+            point(padding as f32, padding as f32 + v_metrics.ascent),
+        )
+        .collect();
+    let width = text_width(&glyphs, padding as f32).ceil() as u32 + padding;
+    let height = (v_metrics.ascent - v_metrics.descent).ceil() as u32 + 2 * padding;
+
+    let mut rgba = BACKGROUND.repeat((width * height) as usize);
+    draw(&glyphs, &mut rgba, width, height);
+    (rgba, width, height)
+}
+
+/// 渲染与原图同尺寸的rgba图层，每个词的文字缩放后居中绘制在其外接矩形内；没有可用字体时返回None
+pub fn words(words: &[Word], width: u32, height: u32) -> Option<Vec<u8>> {
+    let (font, _) = FONT.as_ref().ok()?;
+    let mut rgba = vec![0; (width * height * 4) as usize];
+    for word in words {
+        let right = (word.left + word.width).min(width);
+        let bottom = (word.top + word.height).min(height);
+        for y in word.top..bottom {
+            let row = (y * width) as usize;
+            for x in word.left..right {
+                let offset = (row + x as usize) * 4;
+                rgba[offset..offset + 4].copy_from_slice(&WORD_BACKGROUND);
+            }
+        }
+
+        // 先按词框高度排版，放不下时按宽度缩小
+        let mut size = Scale::uniform(word.height as f32 * 0.8);
+        let natural = text_width(
+            &font
+                .layout(&word.text, size, point(0.0, 0.0))
+                .collect::<Vec<_>>(),
+            0.0,
+        );
+        if natural > word.width as f32 {
+            size = Scale::uniform(size.y * word.width as f32 / natural);
+        }
+        let v_metrics = font.v_metrics(size);
+        let text_height = v_metrics.ascent - v_metrics.descent;
+        let offset = (word.width as f32 - natural.min(word.width as f32)) / 2.0;
+        let origin = point(
+            word.left as f32 + offset,
+            word.top as f32 + (word.height as f32 - text_height) / 2.0 + v_metrics.ascent,
+        );
+        let glyphs: Vec<_> = font.layout(&word.text, size, origin).collect();
+        draw(&glyphs, &mut rgba, width, height);
+    }
+    Some(rgba)
+}
+
+/// 排版后文字右端的横坐标，没有字时为start
+fn text_width(glyphs: &[PositionedGlyph], start: f32) -> f32 {
+    glyphs
+        .last()
+        .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .unwrap_or(start)
+}
+
+/// 白字按覆盖率叠加到rgba位图上
+fn draw(glyphs: &[PositionedGlyph], rgba: &mut [u8], width: u32, height: u32) {
+    for glyph in glyphs {
+        let Some(bounds) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+        glyph.draw(|x, y, coverage| {
+            let x = bounds.min.x + x as i32;
+            let y = bounds.min.y + y as i32;
+            if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+                return;
+            }
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            let pixel = &mut rgba[offset..offset + 4];
+            for (channel, &foreground) in pixel.iter_mut().zip(FOREGROUND.iter()) {
+                let value = *channel as f32 + (foreground as f32 - *channel as f32) * coverage;
+                *channel = (*channel).max(value.round() as u8);
+            }
+        });
+    }
+}
@@ -1,19 +1,41 @@
-use std::{iter, thread, time::Instant};
+use std::{
+    iter, thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
+use log_error::LogError;
 use screenshots::Image;
-use tracing::info;
+use tracing::{info, warn};
 use wgpu::util::DeviceExt;
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
     event_loop::EventLoopProxy,
     window::{Window, WindowId},
 };
 
-use crate::{event::Event, image::ImageExt, util};
+use crate::{
+    config,
+    event::Event,
+    image::ImageExt,
+    lens::Lens,
+    ocr::{self, Cancel, Request, Word},
+    util,
+};
 
+mod context;
+mod gallery;
+mod glyph;
+mod overlay;
+mod soft;
 mod texture;
 
+pub use context::{probe, Context};
+pub use gallery::{Action, Gallery};
+pub use glyph::font_name;
+pub use overlay::Overlay;
+use soft::SoftSurface;
+
 const VERTICES: &[Vertex] = &[
     Vertex {
         position: [-1.0, 1.0, 0.0],
@@ -35,6 +57,31 @@ const VERTICES: &[Vertex] = &[
 
 const INDICES: &[u16] = &[1, 2, 0, 0, 2, 3];
 
+const LABEL_SCALE: u32 = 2;
+const LABEL_MARGIN: f32 = 4.0;
+
+/// 滚轮缩放依次经过的比例
+const ZOOM_LEVELS: &[f32] = &[
+    0.25, 0.33, 0.5, 0.67, 0.75, 1.0, 1.25, 1.5, 2.0, 3.0, 4.0, 6.0, 8.0,
+];
+/// Ctrl+滚轮调节不透明度的步长与下限
+const OPACITY_STEP: f32 = 0.1;
+const MIN_OPACITY: f32 = 0.1;
+/// 识别到的词、置信度低的词与选中词的底色
+const WORD_COLOR: [f32; 4] = [0.1, 0.6, 1.0, 0.12];
+const LOW_CONFIDENCE_COLOR: [f32; 4] = [1.0, 0.55, 0.0, 0.35];
+const SELECTED_COLOR: [f32; 4] = [0.1, 0.6, 1.0, 0.4];
+
+/// 窗口像素坐标的矩形（左、上、右、下）及颜色
+type Shape = ((f32, f32, f32, f32), [f32; 4]);
+
+/// 每个贴图保留的撤销步数
+const MAX_UNDO: usize = 100;
+/// 缩放或调节不透明度后显示提示的时长
+const HINT_DURATION: Duration = Duration::from_millis(1500);
+/// 左上角显示选中或光标所在文字的最大字数
+const MAX_LABEL_CHARS: usize = 40;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
@@ -64,6 +111,36 @@ impl Vertex {
     }
 }
 
+/// 纯色图形顶点，用于选区遮罩与边框
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShapeVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+impl ShapeVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<ShapeVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Zeroable, bytemuck::Pod)]
 struct Uniforms {
@@ -76,329 +153,359 @@ impl Uniforms {
     }
 }
 
-pub struct State {
+/// 贴图着色参数，对应shader.wgsl中的Pin
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct PinUniforms {
+    opacity: f32,
+    premultiplied: f32,
+}
+
+impl Default for PinUniforms {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            premultiplied: 0.0,
+        }
+    }
+}
+
+impl PinUniforms {
+    fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+}
+
+/// 文字标签纹理，文字不变时复用
+struct Label {
+    text: String,
+    width: u32,
+    height: u32,
+    /// bind_group使用期间需保留
+    _texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Label {
+    fn new(context: &Context, text: String) -> Result<Self> {
+        let (rgba, width, height) = glyph::label(&text, LABEL_SCALE);
+        let texture = texture::Texture::from_image(
+            &context.device,
+            &context.queue,
+            &rgba,
+            width,
+            height,
+            Some("label"),
+        )?;
+        let bind_group = context.texture_bind_group(&texture);
+        Ok(Self {
+            text,
+            width,
+            height,
+            _texture: texture,
+            bind_group,
+        })
+    }
+}
+
+/// 识别到的文字图层纹理，与原图同尺寸
+struct TextLayer {
+    /// bind_group使用期间需保留
+    _texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TextLayer {
+    fn new(context: &Context, rgba: &[u8], width: u32, height: u32) -> Result<Self> {
+        let texture = texture::Texture::from_image(
+            &context.device,
+            &context.queue,
+            rgba,
+            width,
+            height,
+            Some("text layer"),
+        )?;
+        let bind_group = context.texture_bind_group(&texture);
+        Ok(Self {
+            _texture: texture,
+            bind_group,
+        })
+    }
+}
+
+/// 窗口像素坐标转裁剪空间坐标
+fn to_clip(size: PhysicalSize<u32>, x: f32, y: f32) -> [f32; 2] {
+    [
+        x / size.width as f32 * 2.0 - 1.0,
+        1.0 - y / size.height as f32 * 2.0,
+    ]
+}
+
+/// 窗口像素坐标的纯色矩形，按两个三角形展开
+fn push_rect(
+    size: PhysicalSize<u32>,
+    shapes: &mut Vec<ShapeVertex>,
+    (left, top, right, bottom): (f32, f32, f32, f32),
+    color: [f32; 4],
+) {
+    if right <= left || bottom <= top {
+        return;
+    }
+    let a = to_clip(size, left, top);
+    let b = to_clip(size, left, bottom);
+    let c = to_clip(size, right, bottom);
+    let d = to_clip(size, right, top);
+    for position in [b, c, a, a, c, d] {
+        shapes.push(ShapeVertex { position, color });
+    }
+}
+
+/// 窗口像素坐标的矩形，按两个三角形展开为贴图顶点
+fn textured_rect(
+    size: PhysicalSize<u32>,
+    (left, top, right, bottom): (f32, f32, f32, f32),
+) -> [Vertex; 6] {
+    let vertex = |x, y, u, v| {
+        let [x, y] = to_clip(size, x, y);
+        Vertex {
+            position: [x, y, 0.0],
+            tex_coords: [u, v],
+        }
+    };
+    let a = vertex(left, top, 0.0, 0.0);
+    let b = vertex(left, bottom, 0.0, 1.0);
+    let c = vertex(right, bottom, 1.0, 1.0);
+    let d = vertex(right, top, 1.0, 0.0);
+    [b, c, a, a, c, d]
+}
+
+/// 渲染方式，首个窗口创建时选定：优先GPU（含软件适配器），其次CPU拷贝
+pub enum Renderer {
+    Gpu(Box<Context>),
+    Soft,
+}
+
+/// 窗口的显示目标
+pub enum Target {
+    Gpu(wgpu::Surface),
+    Soft(SoftSurface),
+}
+
+impl Renderer {
+    /// 依次尝试GPU适配器、软件适配器与CPU拷贝，同时返回该窗口的显示目标
+    pub fn new(window: &Window) -> Result<(Self, Target)> {
+        match pollster::block_on(Context::new(window)) {
+            Result::Ok((context, surface)) => {
+                Ok((Renderer::Gpu(Box::new(context)), Target::Gpu(surface)))
+            }
+            Err(e) => {
+                warn!("{e:#}，改用CPU绘制");
+                let surface = SoftSurface::new(window)?;
+                Ok((Renderer::Soft, Target::Soft(surface)))
+            }
+        }
+    }
+
+    /// 为后续窗口创建显示目标，GPU不支持该窗口时改用CPU拷贝
+    pub fn target(&self, window: &Window) -> Result<Target> {
+        match self {
+            Renderer::Gpu(context) => match context.create_surface(window) {
+                Result::Ok(surface) => Ok(Target::Gpu(surface)),
+                Err(e) => {
+                    warn!("{e:#}，该窗口改用CPU绘制");
+                    SoftSurface::new(window).map(Target::Soft)
+                }
+            },
+            Renderer::Soft => SoftSurface::new(window).map(Target::Soft),
+        }
+    }
+
+    pub fn context(&self) -> Option<&Context> {
+        match self {
+            Renderer::Gpu(context) => Some(context),
+            Renderer::Soft => None,
+        }
+    }
+}
+
+/// GPU绘制时每个窗口的surface、纹理与uniform
+struct GpuSurface {
     surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    animation_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    #[allow(dead_code)]
     diffuse_texture: texture::Texture,
     uniforms_buffer: wgpu::Buffer,
     diffuse_bind_group: wgpu::BindGroup,
+    nearest_bind_group: wgpu::BindGroup,
     uniforms_bind_group: wgpu::BindGroup,
-    window: Window,
-    image: Image,
-    ocring: bool,
-    instant: Instant,
     uniforms: Uniforms,
+    pin_buffer: wgpu::Buffer,
+    pin_bind_group: wgpu::BindGroup,
+    pin: PinUniforms,
+    label: Option<Label>,
+    text_layer: Option<TextLayer>,
 }
 
-impl State {
-    pub async fn new(window: Window, image: Image, size: PhysicalSize<u32>) -> Self {
-        // The instance is a handle to our GPU
-        // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
-        let backends = if cfg!(windows) {
-            // Vulkan在win11上不稳定，会出现白窗
-            wgpu::Backends::DX12
-        } else {
-            wgpu::Backends::all()
-        };
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends,
-            ..Default::default()
-        });
+enum Surface {
+    Gpu(Box<GpuSurface>),
+    Soft(SoftSurface),
+}
 
-        // # Safety
-        //
-        // The surface needs to live as long as the window that created it.
-        // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: wgpu::Limits::default(),
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
-
-        let surface_caps = surface.get_capabilities(&adapter);
-        // Shader code in this tutorial assumes an Srgb surface texture. Using a different
-        // one will result all the colors coming out darker. If you want to support non
-        // Srgb surfaces, you'll need to account for that when drawing to the frame.
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
-        surface.configure(&device, &config);
+impl GpuSurface {
+    fn new(
+        context: &Context,
+        surface: wgpu::Surface,
+        image: &Image,
+        size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        // 按透明方式合成，调节不透明度时可透出下层窗口
+        let config = context.configure(&surface, size, true)?;
 
-        let rgba = image.rgba();
-        let width = image.width();
-        let height = image.height();
         let diffuse_texture = texture::Texture::from_image(
-            &device,
-            &queue,
-            rgba,
-            width,
-            height,
+            &context.device,
+            &context.queue,
+            image.rgba(),
+            image.width(),
+            image.height(),
             Some("right screenshot"),
-        )
-        .unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        )?;
+        let diffuse_bind_group = context.texture_bind_group(&diffuse_texture);
+        let nearest_bind_group = context.nearest_bind_group(&diffuse_texture);
 
         let uniforms = Uniforms::default();
-
-        let uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: uniforms.as_bytes(),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let uniforms_buffer_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let uniforms_buffer =
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: uniforms.as_bytes(),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+        let uniforms_bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
+                layout: &context.uniforms_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    count: None,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
+                    resource: uniforms_buffer.as_entire_binding(),
                 }],
             });
 
-        let uniforms_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &uniforms_buffer_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniforms_buffer.as_entire_binding(),
-            }],
-        });
-
-        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let animation_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Animation"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("animation.wgsl").into()),
-        });
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout],
-                push_constant_ranges: &[],
+        let pin = PinUniforms {
+            premultiplied: if config.alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied {
+                1.0
+            } else {
+                0.0
+            },
+            ..Default::default()
+        };
+        let pin_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Pin Buffer"),
+                contents: pin.as_bytes(),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
-
-        let animation_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Animation Pipeline Layout"),
-                bind_group_layouts: &[&uniforms_buffer_layout],
-                push_constant_ranges: &[],
+        let pin_bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("pin_bind_group"),
+                layout: &context.uniforms_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: pin_buffer.as_entire_binding(),
+                }],
             });
 
-        let create_pipeline = |module, pipeline_layout| {
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module,
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::REPLACE,
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                    // or Features::POLYGON_MODE_POINT
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    // Requires Features::DEPTH_CLIP_CONTROL
-                    unclipped_depth: false,
-                    // Requires Features::CONSERVATIVE_RASTERIZATION
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                // If the pipeline will be used with a multiview render pass, this
-                // indicates how many array layers the attachments will have.
-                multiview: None,
-            })
-        };
-
-        let render_pipeline = create_pipeline(&module, &render_pipeline_layout);
-        let animation_pipeline = create_pipeline(&animation_module, &animation_pipeline_layout);
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let num_indices = INDICES.len() as u32;
-
-        Self {
+        Ok(Self {
             surface,
-            device,
-            queue,
             config,
-            size,
-            render_pipeline,
-            animation_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
             diffuse_texture,
             uniforms_buffer,
             diffuse_bind_group,
+            nearest_bind_group,
             uniforms_bind_group,
-            window,
-            image,
-            ocring: false,
-            instant: Instant::now(),
             uniforms,
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-        }
-    }
-
-    pub fn visible(&self) {
-        self.window.set_visible(true);
+            pin_buffer,
+            pin_bind_group,
+            pin,
+            label: None,
+            text_layer: None,
+        })
     }
 
-    pub fn ocr(&mut self, event_loop: EventLoopProxy<Event>) -> Result<()> {
-        if !self.ocring {
-            let tiff = self.image.to_tiff()?;
-            let window_id = Self::get_id(self);
-            thread::spawn(move || {
-                event_loop.send_event(Event::Redraw(window_id))?;
-                util::ocr(&tiff).and_then(util::copy_text)?;
-                event_loop.send_event(Event::Close(window_id))?;
-                Ok(())
-            });
-            self.ocring = true;
-        }
-
+    fn set_image(&mut self, context: &Context, image: &Image) -> Result<()> {
+        self.diffuse_texture = texture::Texture::from_image(
+            &context.device,
+            &context.queue,
+            image.rgba(),
+            image.width(),
+            image.height(),
+            Some("right screenshot"),
+        )?;
+        self.diffuse_bind_group = context.texture_bind_group(&self.diffuse_texture);
+        self.nearest_bind_group = context.nearest_bind_group(&self.diffuse_texture);
         Ok(())
     }
 
-    pub fn get_id(&self) -> WindowId {
-        self.window.id()
+    fn set_opacity(&mut self, context: &Context, opacity: f32) {
+        self.pin.opacity = opacity;
+        context
+            .queue
+            .write_buffer(&self.pin_buffer, 0, self.pin.as_bytes());
     }
 
-    pub fn ocring(&self) -> bool {
-        self.ocring
-    }
+    /// nearest为true时按像素放大，识别到的文字图层与shapes依次叠加在画面上，label显示在左上角
+    fn render(
+        &mut self,
+        context: &Context,
+        ocring: Option<f32>,
+        nearest: bool,
+        shapes: &[Shape],
+        label: Option<String>,
+    ) -> Result<()> {
+        match label {
+            Some(text) if !matches!(&self.label, Some(label) if label.text == text) => {
+                self.label = Some(Label::new(context, text)?);
+            }
+            None => self.label = None,
+            _ => {}
+        }
+        let size = PhysicalSize::new(self.config.width, self.config.height);
+        let mut vertices = Vec::new();
+        for &(rect, color) in shapes {
+            push_rect(size, &mut vertices, rect, color);
+        }
+        let shape_buffer = (!vertices.is_empty()).then(|| {
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Shape Buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+        });
+        let label_buffer = self.label.as_ref().map(|label| {
+            let (x, y) = (LABEL_MARGIN, LABEL_MARGIN);
+            let vertices = textured_rect(
+                size,
+                (x, y, x + label.width as f32, y + label.height as f32),
+            );
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Label Buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+        });
 
-    pub fn render(&mut self) -> Result<()> {
         let output = self.surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let mut encoder = self
+        let mut encoder = context
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
@@ -418,28 +525,594 @@ impl State {
                 depth_stencil_attachment: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
-            if self.ocring {
-                self.uniforms.time = self.instant.elapsed().as_secs_f32();
-                self.queue
+            render_pass.set_pipeline(&context.render_pipeline);
+            let bind_group = if nearest {
+                &self.nearest_bind_group
+            } else {
+                &self.diffuse_bind_group
+            };
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_bind_group(1, &self.pin_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, context.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(context.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..context.num_indices, 0, 0..1);
+            if let Some(time) = ocring {
+                self.uniforms.time = time;
+                context
+                    .queue
                     .write_buffer(&self.uniforms_buffer, 0, self.uniforms.as_bytes());
-                render_pass.set_pipeline(&self.animation_pipeline);
+                render_pass.set_pipeline(&context.animation_pipeline);
                 render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(0, context.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(context.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..context.num_indices, 0, 0..1);
+            }
+            if let Some(text_layer) = &self.text_layer {
+                render_pass.set_pipeline(&context.blend_pipeline);
+                render_pass.set_bind_group(0, &text_layer.bind_group, &[]);
+                render_pass.set_bind_group(1, &context.opaque_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, context.vertex_buffer.slice(..));
                 render_pass
-                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+                    .set_index_buffer(context.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..context.num_indices, 0, 0..1);
+            }
+            if let Some(shape_buffer) = &shape_buffer {
+                render_pass.set_pipeline(&context.shape_pipeline);
+                render_pass.set_vertex_buffer(0, shape_buffer.slice(..));
+                render_pass.draw(0..vertices.len() as u32, 0..1);
+            }
+            if let (Some(label), Some(label_buffer)) = (&self.label, &label_buffer) {
+                render_pass.set_pipeline(&context.blend_pipeline);
+                render_pass.set_bind_group(0, &label.bind_group, &[]);
+                render_pass.set_bind_group(1, &context.opaque_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, label_buffer.slice(..));
+                render_pass.draw(0..6, 0..1);
             }
         }
 
-        info!("render, ocring: {}", self.ocring);
-
-        self.queue.submit(iter::once(encoder.finish()));
+        context.queue.submit(iter::once(encoder.finish()));
         output.present();
         Ok(())
     }
 }
+
+/// 单个贴图窗口，设备和管线来自共享的Context，GPU不可用时用CPU拷贝显示
+pub struct State {
+    surface: Surface,
+    size: winit::dpi::PhysicalSize<u32>,
+    window: Window,
+    image: Image,
+    /// 对应的历史记录，OCR结果写回该记录
+    history: Option<u64>,
+    /// 截图在屏幕上的位置，贴图移动后不变，OCR结果按此换算屏幕坐标
+    source: Option<Lens>,
+    /// 进行中的识别
+    ocr_job: Option<OcrJob>,
+    /// 已发起的识别次数，用作识别的编号
+    ocr_jobs: u64,
+    instant: Instant,
+    /// 缩放比例，1.0为原始尺寸
+    zoom: f32,
+    opacity: f32,
+    /// 左上角短暂显示的提示及其显示时间
+    hint: Option<(String, Instant)>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// 保留贴图时识别到的词
+    words: Vec<Word>,
+    /// 词框内绘制识别文字的rgba图层，与原图同尺寸，没有可用字体时为None
+    text_layer: Option<Vec<u8>>,
+    /// 选中词的起止序号，按拖动方向
+    selection: Option<(usize, usize)>,
+    /// 光标所在词的序号
+    hovered: Option<usize>,
+    low_confidence: f32,
+}
+
+/// 一次后台识别，完成事件带编号，已被取消或取代的识别结果不再处理
+struct OcrJob {
+    id: u64,
+    cancel: Cancel,
+    /// 完成后保留贴图并标出识别到的词，否则关闭窗口
+    show_words: bool,
+}
+
+/// 可撤销的贴图调整：位置、缩放与不透明度
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Snapshot {
+    position: PhysicalPosition<i32>,
+    zoom: f32,
+    opacity: f32,
+}
+
+impl State {
+    pub fn new(
+        renderer: &Renderer,
+        window: Window,
+        target: Target,
+        image: Image,
+        size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        let surface = match (renderer, target) {
+            (Renderer::Gpu(context), Target::Gpu(surface)) => {
+                Surface::Gpu(Box::new(GpuSurface::new(context, surface, &image, size)?))
+            }
+            (_, Target::Soft(surface)) => Surface::Soft(surface),
+            (Renderer::Soft, Target::Gpu(_)) => return Err(anyhow!("渲染方式与显示目标不一致")),
+        };
+
+        Ok(Self {
+            surface,
+            size,
+            window,
+            image,
+            history: None,
+            source: None,
+            ocr_job: None,
+            ocr_jobs: 0,
+            instant: Instant::now(),
+            zoom: 1.0,
+            opacity: 1.0,
+            hint: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            words: Vec::new(),
+            text_layer: None,
+            selection: None,
+            hovered: None,
+            low_confidence: 0.0,
+        })
+    }
+
+    pub fn resize(&mut self, renderer: &Renderer, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            if let (Surface::Gpu(gpu), Some(context)) = (&mut self.surface, renderer.context()) {
+                gpu.config.width = new_size.width;
+                gpu.config.height = new_size.height;
+                gpu.surface.configure(&context.device, &gpu.config);
+            }
+        }
+    }
+
+    /// 按滚轮方向切换到相邻的缩放比例
+    pub fn zoom_by(
+        &mut self,
+        renderer: &Renderer,
+        steps: i32,
+        anchor: PhysicalPosition<f64>,
+        event_loop: EventLoopProxy<Event>,
+    ) -> Result<()> {
+        let zoom = if steps > 0 {
+            ZOOM_LEVELS.iter().copied().find(|&z| z > self.zoom)
+        } else {
+            ZOOM_LEVELS.iter().rev().copied().find(|&z| z < self.zoom)
+        };
+        match zoom {
+            Some(zoom) => self.set_zoom(renderer, zoom, anchor, event_loop),
+            None => Ok(()),
+        }
+    }
+
+    /// 缩放窗口，锚点（窗口内坐标）下的画面位置保持不变
+    pub fn set_zoom(
+        &mut self,
+        renderer: &Renderer,
+        zoom: f32,
+        anchor: PhysicalPosition<f64>,
+        event_loop: EventLoopProxy<Event>,
+    ) -> Result<()> {
+        if zoom == self.zoom {
+            return Ok(());
+        }
+        self.checkpoint()?;
+        self.apply_zoom(renderer, zoom, anchor)?;
+        self.show_hint(format!("{:.0}%", zoom * 100.0), event_loop)
+    }
+
+    fn apply_zoom(
+        &mut self,
+        renderer: &Renderer,
+        zoom: f32,
+        anchor: PhysicalPosition<f64>,
+    ) -> Result<()> {
+        let size = PhysicalSize::new(
+            ((self.image.width() as f32 * zoom).round() as u32).max(1),
+            ((self.image.height() as f32 * zoom).round() as u32).max(1),
+        );
+        let origin = self.window.outer_position()?;
+        let ratio_x = size.width as f64 / self.size.width as f64;
+        let ratio_y = size.height as f64 / self.size.height as f64;
+        let x = origin.x + (anchor.x - anchor.x * ratio_x).round() as i32;
+        let y = origin.y + (anchor.y - anchor.y * ratio_y).round() as i32;
+        self.window.set_outer_position(PhysicalPosition::new(x, y));
+        self.window.set_inner_size(size);
+        self.resize(renderer, size);
+        self.zoom = zoom;
+        Ok(())
+    }
+
+    /// 按滚轮方向调节不透明度，CPU绘制时不支持
+    pub fn opacity_by(
+        &mut self,
+        renderer: &Renderer,
+        steps: i32,
+        event_loop: EventLoopProxy<Event>,
+    ) -> Result<()> {
+        let opacity = self.opacity + steps as f32 * OPACITY_STEP;
+        // 消除累加误差，保持整十百分比
+        self.set_opacity(renderer, (opacity * 10.0).round() / 10.0, event_loop)
+    }
+
+    pub fn set_opacity(
+        &mut self,
+        renderer: &Renderer,
+        opacity: f32,
+        event_loop: EventLoopProxy<Event>,
+    ) -> Result<()> {
+        let opacity = opacity.clamp(MIN_OPACITY, 1.0);
+        if opacity == self.opacity || !matches!(self.surface, Surface::Gpu(_)) {
+            return Ok(());
+        }
+        self.checkpoint()?;
+        self.apply_opacity(renderer, opacity);
+        self.show_hint(format!("{:.0}%", opacity * 100.0), event_loop)
+    }
+
+    fn apply_opacity(&mut self, renderer: &Renderer, opacity: f32) {
+        if let (Surface::Gpu(gpu), Some(context)) = (&mut self.surface, renderer.context()) {
+            gpu.set_opacity(context, opacity);
+            self.opacity = opacity;
+        }
+    }
+
+    fn snapshot(&self) -> Result<Snapshot> {
+        Ok(Snapshot {
+            position: self.window.outer_position()?,
+            zoom: self.zoom,
+            opacity: self.opacity,
+        })
+    }
+
+    /// 记录调整前的状态，供撤销
+    pub fn checkpoint(&mut self) -> Result<()> {
+        let snapshot = self.snapshot()?;
+        if self.undo.last() != Some(&snapshot) {
+            self.undo.push(snapshot);
+            if self.undo.len() > MAX_UNDO {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        Ok(())
+    }
+
+    pub fn undo(&mut self, renderer: &Renderer, event_loop: EventLoopProxy<Event>) -> Result<()> {
+        let current = self.snapshot()?;
+        // 按下后未拖动等操作会留下与当前相同的记录，跳过
+        while let Some(snapshot) = self.undo.pop() {
+            if snapshot != current {
+                self.redo.push(current);
+                return self.restore(renderer, snapshot, event_loop);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn redo(&mut self, renderer: &Renderer, event_loop: EventLoopProxy<Event>) -> Result<()> {
+        let Some(snapshot) = self.redo.pop() else {
+            return Ok(());
+        };
+        let current = self.snapshot()?;
+        self.undo.push(current);
+        self.restore(renderer, snapshot, event_loop)
+    }
+
+    fn restore(
+        &mut self,
+        renderer: &Renderer,
+        snapshot: Snapshot,
+        event_loop: EventLoopProxy<Event>,
+    ) -> Result<()> {
+        self.apply_zoom(renderer, snapshot.zoom, PhysicalPosition::new(0.0, 0.0))?;
+        self.window.set_outer_position(snapshot.position);
+        self.apply_opacity(renderer, snapshot.opacity);
+        event_loop.send_event(Event::Redraw(self.get_id()))?;
+        Ok(())
+    }
+
+    /// 立即重绘显示提示，到期后再重绘一次将其隐藏
+    fn show_hint(&mut self, text: String, event_loop: EventLoopProxy<Event>) -> Result<()> {
+        self.hint = Some((text, Instant::now()));
+        let window_id = self.get_id();
+        event_loop.send_event(Event::Redraw(window_id))?;
+        thread::spawn(move || {
+            thread::sleep(HINT_DURATION);
+            event_loop
+                .send_event(Event::Redraw(window_id))
+                .log_error("发起重绘失败");
+        });
+        Ok(())
+    }
+
+    /// 鼠标穿透时点击落到下层窗口，贴图不再接收鼠标输入
+    pub fn set_click_through(&self, click_through: bool) -> Result<()> {
+        self.window.set_cursor_hittest(!click_through)?;
+        Ok(())
+    }
+
+    /// 当前尺寸的中心
+    pub fn center(&self) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(self.size.width as f64 / 2.0, self.size.height as f64 / 2.0)
+    }
+
+    pub fn visible(&self) {
+        self.window.set_visible(true);
+    }
+
+    pub fn set_history(&mut self, history: Option<u64>) {
+        self.history = history;
+    }
+
+    pub fn set_source(&mut self, lens: Lens) {
+        self.source = Some(lens);
+    }
+
+    /// 替换显示的图片，尺寸需与窗口一致
+    pub fn set_image(&mut self, renderer: &Renderer, image: Image) -> Result<()> {
+        if let (Surface::Gpu(gpu), Some(context)) = (&mut self.surface, renderer.context()) {
+            gpu.set_image(context, &image)?;
+        }
+        self.image = image;
+        Ok(())
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// 贴图当前在屏幕上的位置，尺寸取原图
+    pub fn lens(&self) -> Result<Lens> {
+        let position = self.window.inner_position()?;
+        Ok(Lens {
+            x: position.x,
+            y: position.y,
+            width: self.image.width(),
+            height: self.image.height(),
+        })
+    }
+
+    /// 后台识别，完成后交由事件循环输出结果并关闭窗口或标出识别到的词；已有识别进行中时先取消
+    pub fn ocr(&mut self, event_loop: EventLoopProxy<Event>, request: Request) -> Result<()> {
+        self.cancel_ocr();
+        let image = self.image.to_rgba_image()?;
+        let window_id = Self::get_id(self);
+        let history = self.history;
+        let lens = match self.source {
+            Some(lens) => lens,
+            None => self.lens()?,
+        };
+        let config = config::current();
+        let format = config.ocr.format;
+        self.ocr_jobs += 1;
+        let job = OcrJob {
+            id: self.ocr_jobs,
+            cancel: Cancel::default(),
+            show_words: config.ocr.show_words,
+        };
+        let (id, cancel) = (job.id, job.cancel.clone());
+        thread::spawn(move || {
+            event_loop.send_event(Event::Redraw(window_id))?;
+            let Some(result) =
+                util::log_ocr(util::ocr(image, lens, history, &request, format, &cancel))
+            else {
+                event_loop.send_event(Event::OcrFailed(window_id, id))?;
+                return Ok(());
+            };
+            event_loop.send_event(Event::OcrDone(window_id, id, result))?;
+            Ok(())
+        });
+        self.ocr_job = Some(job);
+
+        Ok(())
+    }
+
+    pub fn ocring(&self) -> bool {
+        self.ocr_job.is_some()
+    }
+
+    /// 取消进行中的识别，返回是否有识别被取消；停止动画需另行重绘
+    pub fn cancel_ocr(&mut self) -> bool {
+        let Some(job) = self.ocr_job.take() else {
+            return false;
+        };
+        job.cancel.cancel();
+        info!("取消OCR");
+        true
+    }
+
+    /// 识别结束；返回完成后是否保留贴图，job不是进行中的识别时返回None
+    pub fn finish_ocr(&mut self, job: u64) -> Option<bool> {
+        if self.ocr_job.as_ref()?.id != job {
+            return None;
+        }
+        let job = self.ocr_job.take()?;
+        Some(job.show_words)
+    }
+
+    /// 识别完成，保留贴图，在词框内显示识别到的文字
+    pub fn set_words(&mut self, renderer: &Renderer, words: Vec<Word>) -> Result<()> {
+        let (width, height) = (self.image.width(), self.image.height());
+        self.text_layer = glyph::words(&words, width, height);
+        if let (Surface::Gpu(gpu), Some(context)) = (&mut self.surface, renderer.context()) {
+            gpu.text_layer = self
+                .text_layer
+                .as_ref()
+                .map(|rgba| TextLayer::new(context, rgba, width, height))
+                .transpose()?;
+        }
+        self.words = words;
+        self.selection = None;
+        self.hovered = None;
+        self.low_confidence = config::current().ocr.low_confidence;
+        Ok(())
+    }
+
+    /// 窗口坐标转图片坐标
+    fn to_image(&self, position: PhysicalPosition<f64>) -> (f64, f64) {
+        (
+            position.x * self.image.width() as f64 / self.size.width as f64,
+            position.y * self.image.height() as f64 / self.size.height as f64,
+        )
+    }
+
+    /// 在词上按下时开始选择，返回是否开始
+    pub fn select_from(&mut self, position: PhysicalPosition<f64>) -> bool {
+        let (x, y) = self.to_image(position);
+        self.selection = self
+            .words
+            .iter()
+            .position(|word| word.contains(x, y))
+            .map(|index| (index, index));
+        self.selection.is_some()
+    }
+
+    /// 拖动时选到离光标最近的词
+    pub fn select_to(&mut self, position: PhysicalPosition<f64>) {
+        let (x, y) = self.to_image(position);
+        let nearest = self
+            .words
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.distance(x, y).total_cmp(&b.distance(x, y)))
+            .map(|(index, _)| index);
+        if let (Some((start, _)), Some(end)) = (self.selection, nearest) {
+            self.selection = Some((start, end));
+        }
+    }
+
+    /// 选中的文字，未选择时为None
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection?;
+        let (start, end) = (start.min(end), start.max(end));
+        self.words.get(start..=end).map(ocr::join)
+    }
+
+    /// 更新光标所在的词，光标离开窗口时为None，返回是否变化
+    pub fn hover(&mut self, position: Option<PhysicalPosition<f64>>) -> bool {
+        let hovered = position.and_then(|position| {
+            let (x, y) = self.to_image(position);
+            self.words.iter().position(|word| word.contains(x, y))
+        });
+        let changed = hovered != self.hovered;
+        self.hovered = hovered;
+        changed
+    }
+
+    /// 左上角显示的文字：选中的文字，未选择时为光标所在的词，合并为一行并截断
+    fn words_label(&self) -> Option<String> {
+        let text = self.selected_text().or_else(|| {
+            self.hovered
+                .and_then(|index| self.words.get(index))
+                .map(|word| word.text.clone())
+        })?;
+        let text = text.replace('\n', " ");
+        if text.chars().count() > MAX_LABEL_CHARS {
+            let truncated: String = text.chars().take(MAX_LABEL_CHARS - 1).collect();
+            Some(format!("{truncated}…"))
+        } else {
+            Some(text)
+        }
+    }
+
+    /// 选中的文字，未选择时为全部识别文字
+    pub fn text(&self) -> Option<String> {
+        self.selected_text()
+            .or_else(|| (!self.words.is_empty()).then(|| ocr::join(&self.words)))
+    }
+
+    /// 词的底色，选中的词优先，其次置信度低的词
+    fn word_shapes(&self) -> Vec<Shape> {
+        let scale_x = self.size.width as f32 / self.image.width() as f32;
+        let scale_y = self.size.height as f32 / self.image.height() as f32;
+        let selected = self
+            .selection
+            .map(|(start, end)| start.min(end)..=start.max(end));
+        self.words
+            .iter()
+            .enumerate()
+            .map(|(index, word)| {
+                let rect = (
+                    word.left as f32 * scale_x,
+                    word.top as f32 * scale_y,
+                    (word.left + word.width) as f32 * scale_x,
+                    (word.top + word.height) as f32 * scale_y,
+                );
+                let color = if selected
+                    .as_ref()
+                    .is_some_and(|range| range.contains(&index))
+                {
+                    SELECTED_COLOR
+                } else if word.conf < self.low_confidence {
+                    LOW_CONFIDENCE_COLOR
+                } else {
+                    WORD_COLOR
+                };
+                (rect, color)
+            })
+            .collect()
+    }
+
+    pub fn get_id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// OCR进行中需要持续重绘动画，CPU拷贝时只绘制一次变暗画面
+    pub fn animating(&self) -> bool {
+        self.ocring() && matches!(self.surface, Surface::Gpu(_))
+    }
+
+    pub fn render(&mut self, renderer: &Renderer) -> Result<()> {
+        let ocring = self.ocring().then(|| self.instant.elapsed().as_secs_f32());
+        let label = self
+            .hint
+            .as_ref()
+            .filter(|(_, shown)| shown.elapsed() < HINT_DURATION)
+            .map(|(text, _)| text.clone())
+            .or_else(|| self.words_label());
+        // 整数倍缩放时逐像素放大，保持文字边缘清晰
+        let nearest = self.zoom.fract() == 0.0;
+        let shapes = self.word_shapes();
+        match (&mut self.surface, renderer) {
+            (Surface::Gpu(gpu), Renderer::Gpu(context)) => {
+                gpu.render(context, ocring, nearest, &shapes, label)?
+            }
+            (Surface::Soft(soft), _) => soft.present(
+                (self.image.rgba(), self.image.width(), self.image.height()),
+                self.size,
+                ocring.is_some(),
+                self.text_layer.as_deref(),
+                &shapes,
+                label.as_deref(),
+            )?,
+            (Surface::Gpu(_), Renderer::Soft) => return Err(anyhow!("渲染方式与显示目标不一致")),
+        }
+
+        info!("render, ocring: {}", self.ocring());
+        Ok(())
+    }
+}
+
+impl Drop for State {
+    /// 关闭贴图时取消其识别，结果不再写入剪切板
+    fn drop(&mut self) {
+        if let Some(job) = self.ocr_job.take() {
+            job.cancel.cancel();
+        }
+    }
+}
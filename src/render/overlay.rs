@@ -0,0 +1,164 @@
+use std::iter;
+
+use anyhow::{Ok, Result};
+use wgpu::util::DeviceExt;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
+};
+
+use super::{push_rect, textured_rect, Context, Label, ShapeVertex, LABEL_MARGIN};
+use crate::lens::Lens;
+
+const DIM_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+const BORDER_COLOR: [f32; 4] = [0.1, 0.6, 1.0, 1.0];
+const BORDER_WIDTH: f32 = 2.0;
+
+/// 拖动选区时覆盖整个虚拟桌面的透明窗口，鼠标穿透
+pub struct Overlay {
+    surface: wgpu::Surface,
+    label: Option<Label>,
+    window: Window,
+    origin: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+}
+
+impl Overlay {
+    pub fn new(
+        context: &Context,
+        window: Window,
+        surface: wgpu::Surface,
+        origin: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        // 透明窗口需要合成器尊重alpha通道
+        context.configure(&surface, size, true)?;
+        Ok(Self {
+            surface,
+            label: None,
+            window,
+            origin,
+            size,
+        })
+    }
+
+    pub fn visible(&self, visible: bool) {
+        self.window.set_visible(visible);
+    }
+
+    /// 按当前选区重绘：选区外变暗、描边并显示宽×高
+    pub fn render(&mut self, context: &Context, lens: &Lens) -> Result<()> {
+        let left = (lens.x - self.origin.x) as f32;
+        let top = (lens.y - self.origin.y) as f32;
+        let right = left + lens.width as f32;
+        let bottom = top + lens.height as f32;
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+
+        let mut shapes = Vec::new();
+        // 选区外四块遮罩
+        self.push_rect(&mut shapes, (0.0, 0.0, width, top), DIM_COLOR);
+        self.push_rect(&mut shapes, (0.0, bottom, width, height), DIM_COLOR);
+        self.push_rect(&mut shapes, (0.0, top, left, bottom), DIM_COLOR);
+        self.push_rect(&mut shapes, (right, top, width, bottom), DIM_COLOR);
+        // 选区边框
+        let b = BORDER_WIDTH;
+        self.push_rect(
+            &mut shapes,
+            (left - b, top - b, right + b, top),
+            BORDER_COLOR,
+        );
+        self.push_rect(
+            &mut shapes,
+            (left - b, bottom, right + b, bottom + b),
+            BORDER_COLOR,
+        );
+        self.push_rect(&mut shapes, (left - b, top, left, bottom), BORDER_COLOR);
+        self.push_rect(&mut shapes, (right, top, right + b, bottom), BORDER_COLOR);
+
+        self.update_label(context, format!("{}×{}", lens.width, lens.height))?;
+        let label_vertices = self.label.as_ref().map(|label| {
+            let (w, h) = (label.width as f32, label.height as f32);
+            // 优先放在选区左上角外侧，空间不足时放到选区内
+            let y = if top - h - LABEL_MARGIN >= 0.0 {
+                top - h - LABEL_MARGIN
+            } else {
+                top + LABEL_MARGIN
+            };
+            let x = left.clamp(0.0, (width - w).max(0.0));
+            textured_rect(self.size, (x, y, x + w, y + h))
+        });
+
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let shape_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Shape Buffer"),
+                contents: bytemuck::cast_slice(&shapes),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+        let label_buffer = label_vertices.map(|vertices| {
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Label Buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+        });
+
+        let mut encoder = context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Overlay Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&context.shape_pipeline);
+            render_pass.set_vertex_buffer(0, shape_buffer.slice(..));
+            render_pass.draw(0..shapes.len() as u32, 0..1);
+
+            if let (Some(label), Some(label_buffer)) = (&self.label, &label_buffer) {
+                render_pass.set_pipeline(&context.blend_pipeline);
+                render_pass.set_bind_group(0, &label.bind_group, &[]);
+                render_pass.set_bind_group(1, &context.opaque_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, label_buffer.slice(..));
+                render_pass.draw(0..6, 0..1);
+            }
+        }
+
+        context.queue.submit(iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+
+    fn update_label(&mut self, context: &Context, text: String) -> Result<()> {
+        if !matches!(&self.label, Some(label) if label.text == text) {
+            self.label = Some(Label::new(context, text)?);
+        }
+        Ok(())
+    }
+
+    fn push_rect(
+        &self,
+        shapes: &mut Vec<ShapeVertex>,
+        rect: (f32, f32, f32, f32),
+        color: [f32; 4],
+    ) {
+        push_rect(self.size, shapes, rect, color);
+    }
+}
@@ -27,7 +27,20 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+struct Pin {
+    // 整体不透明度
+    opacity: f32,
+    // surface按预乘alpha合成时为1
+    premultiplied: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> pin: Pin;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let alpha = color.a * pin.opacity;
+    let rgb = mix(color.rgb, color.rgb * alpha, pin.premultiplied);
+    return vec4<f32>(rgb, alpha);
 }
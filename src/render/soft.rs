@@ -0,0 +1,109 @@
+use std::num::NonZeroU32;
+
+use anyhow::{anyhow, Result};
+use winit::{dpi::PhysicalSize, window::Window};
+
+use super::{glyph, Shape, LABEL_MARGIN, LABEL_SCALE};
+
+/// CPU拷贝显示，没有可用图形适配器时代替wgpu surface
+pub struct SoftSurface {
+    surface: softbuffer::Surface,
+    /// surface存活期间需保留
+    _context: softbuffer::Context,
+}
+
+impl SoftSurface {
+    pub fn new(window: &Window) -> Result<Self> {
+        // # Safety
+        //
+        // The window state that receives the surface owns the window.
+        let context = unsafe { softbuffer::Context::new(window) }.map_err(|e| anyhow!("{e}"))?;
+        let surface =
+            unsafe { softbuffer::Surface::new(&context, window) }.map_err(|e| anyhow!("{e}"))?;
+        Ok(Self {
+            surface,
+            _context: context,
+        })
+    }
+
+    /// 按最近邻缩放到窗口尺寸后提交，dim为true时画面变暗表示OCR进行中，
+    /// 与原图同尺寸的文字图层text_layer与shapes依次叠加在画面上，label显示在左上角
+    pub fn present(
+        &mut self,
+        (rgba, width, height): (&[u8], u32, u32),
+        size: PhysicalSize<u32>,
+        dim: bool,
+        text_layer: Option<&[u8]>,
+        shapes: &[Shape],
+        label: Option<&str>,
+    ) -> Result<()> {
+        let (Some(window_width), Some(window_height)) =
+            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+        else {
+            return Ok(());
+        };
+        self.surface
+            .resize(window_width, window_height)
+            .map_err(|e| anyhow!("{e}"))?;
+
+        let mut buffer = self.surface.buffer_mut().map_err(|e| anyhow!("{e}"))?;
+        let shift = u32::from(dim);
+        for y in 0..size.height {
+            let source_y = (y as u64 * height as u64 / size.height as u64) as u32;
+            for x in 0..size.width {
+                let source_x = (x as u64 * width as u64 / size.width as u64) as u32;
+                let i = ((source_y * width + source_x) * 4) as usize;
+                let pixel = match rgba.get(i..i + 3) {
+                    Some(&[r, g, b]) => {
+                        ((r as u32 >> shift) << 16)
+                            | ((g as u32 >> shift) << 8)
+                            | (b as u32 >> shift)
+                    }
+                    _ => 0,
+                };
+                buffer[(y * size.width + x) as usize] =
+                    match text_layer.and_then(|layer| layer.get(i..i + 4)) {
+                        Some(&[r, g, b, a]) if a > 0 => blend(pixel, [r, g, b], a as u32),
+                        _ => pixel,
+                    };
+            }
+        }
+        for &((left, top, right, bottom), [r, g, b, a]) in shapes {
+            let a = (a * 255.0) as u32;
+            let right = (right.max(0.0) as u32).min(size.width);
+            let bottom = (bottom.max(0.0) as u32).min(size.height);
+            for y in top.max(0.0) as u32..bottom {
+                for x in left.max(0.0) as u32..right {
+                    let target = &mut buffer[(y * size.width + x) as usize];
+                    *target = blend(*target, [r, g, b].map(|c| (c * 255.0) as u8), a);
+                }
+            }
+        }
+        if let Some(text) = label {
+            let (rgba, label_width, label_height) = glyph::label(text, LABEL_SCALE);
+            let margin = LABEL_MARGIN as u32;
+            for y in 0..label_height.min(size.height.saturating_sub(margin)) {
+                for x in 0..label_width.min(size.width.saturating_sub(margin)) {
+                    let i = ((y * label_width + x) * 4) as usize;
+                    let target = &mut buffer[((y + margin) * size.width + x + margin) as usize];
+                    *target = blend(
+                        *target,
+                        [rgba[i], rgba[i + 1], rgba[i + 2]],
+                        rgba[i + 3] as u32,
+                    );
+                }
+            }
+        }
+        buffer.present().map_err(|e| anyhow!("{e}"))?;
+        Ok(())
+    }
+}
+
+/// 按alpha（0～255）把颜色叠加到0RGB像素上
+fn blend(pixel: u32, color: [u8; 3], alpha: u32) -> u32 {
+    let channel = |src: u8, shift: u32| {
+        let dst = (pixel >> shift) & 0xff;
+        ((src as u32 * alpha + dst * (255 - alpha)) / 255) << shift
+    };
+    channel(color[0], 16) | channel(color[1], 8) | channel(color[2], 0)
+}
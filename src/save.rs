@@ -0,0 +1,269 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::ImageOutputFormat;
+use rfd::FileDialog;
+use screenshots::Image;
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{capture, config::SaveConfig, image::ImageExt, lens::Lens};
+
+/// 本次运行的截图序号，文件已存在时继续递增
+static SEQ: AtomicU32 = AtomicU32::new(1);
+/// Windows文件名中不能使用的字符
+const INVALID_CHARS: [char; 7] = [':', '*', '?', '"', '<', '>', '|'];
+/// Windows保留的设备名，带扩展名时同样不能使用
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 自动保存的图片格式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Bmp,
+    Tiff,
+}
+
+impl SaveFormat {
+    pub const ALL: [SaveFormat; 5] = [
+        SaveFormat::Png,
+        SaveFormat::Jpeg,
+        SaveFormat::Webp,
+        SaveFormat::Bmp,
+        SaveFormat::Tiff,
+    ];
+
+    /// 按扩展名识别格式，不区分大小写
+    fn from_extension(extension: &str) -> Option<SaveFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(SaveFormat::Png),
+            "jpg" | "jpeg" => Some(SaveFormat::Jpeg),
+            "webp" => Some(SaveFormat::Webp),
+            "bmp" => Some(SaveFormat::Bmp),
+            "tif" | "tiff" => Some(SaveFormat::Tiff),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SaveFormat::Png => "png",
+            SaveFormat::Jpeg => "jpg",
+            SaveFormat::Webp => "webp",
+            SaveFormat::Bmp => "bmp",
+            SaveFormat::Tiff => "tiff",
+        }
+    }
+
+    fn output(&self, quality: u8) -> ImageOutputFormat {
+        match self {
+            SaveFormat::Png => ImageOutputFormat::Png,
+            SaveFormat::Jpeg => ImageOutputFormat::Jpeg(quality),
+            SaveFormat::Webp => ImageOutputFormat::WebP,
+            SaveFormat::Bmp => ImageOutputFormat::Bmp,
+            SaveFormat::Tiff => ImageOutputFormat::Tiff,
+        }
+    }
+}
+
+/// 文件名模板中的占位符
+enum Token {
+    Text(String),
+    Date,
+    Time,
+    Seq,
+    Monitor,
+    Width,
+    Height,
+}
+
+/// 解析文件名模板，如"screenshot_{date}_{time}_{seq}"
+fn parse(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            tokens.push(Token::Text(rest[..open].to_string()));
+        }
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| anyhow!("文件名模板缺少}}：{template}"))?
+            + open;
+        tokens.push(match &rest[open + 1..close] {
+            "date" => Token::Date,
+            "time" => Token::Time,
+            "seq" => Token::Seq,
+            "monitor" => Token::Monitor,
+            "width" => Token::Width,
+            "height" => Token::Height,
+            name => bail!("文件名模板占位符未知：{{{name}}}"),
+        });
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+    Ok(tokens)
+}
+
+/// 校验文件名模板，供配置校验使用
+pub fn check_template(template: &str) -> Result<()> {
+    if template.trim().is_empty() {
+        bail!("文件名模板不能为空");
+    }
+    if template.contains(['/', '\\']) {
+        bail!("文件名模板不能包含路径分隔符");
+    }
+    parse(template)?;
+    check_name(template)
+}
+
+/// 检查文件名（不含扩展名）在Windows下是否可用
+fn check_name(name: &str) -> Result<()> {
+    if let Some(c) = name
+        .chars()
+        .find(|c| INVALID_CHARS.contains(c) || c.is_control())
+    {
+        bail!("文件名不能包含字符{c:?}：{name}");
+    }
+    if name.ends_with(['.', ' ']) {
+        bail!("文件名不能以点或空格结尾：{name}");
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        bail!("文件名不能使用系统保留名{stem}：{name}");
+    }
+    Ok(())
+}
+
+/// 按模板生成文件名，monitor从1开始，区域不在任何屏幕内时为0
+fn render(
+    tokens: &[Token],
+    now: &OffsetDateTime,
+    seq: u32,
+    monitor: usize,
+    lens: &Lens,
+) -> Result<String> {
+    let mut name = String::new();
+    for token in tokens {
+        match token {
+            Token::Text(text) => name.push_str(text),
+            Token::Date => name.push_str(&now.format(format_description!("[year][month][day]"))?),
+            Token::Time => {
+                name.push_str(&now.format(format_description!("[hour][minute][second]"))?)
+            }
+            Token::Seq => name.push_str(&format!("{seq:04}")),
+            Token::Monitor => name.push_str(&monitor.to_string()),
+            Token::Width => name.push_str(&lens.width.to_string()),
+            Token::Height => name.push_str(&lens.height.to_string()),
+        }
+    }
+    // 占位符展开后才可能组成保留名，如"COM{monitor}"
+    check_name(&name)?;
+    Ok(name)
+}
+
+/// 按配置保存截图，未开启时返回None；保存为png时直接写入已编码的png
+pub fn auto_save(
+    image: &Image,
+    png: Option<&[u8]>,
+    lens: &Lens,
+    config: &SaveConfig,
+) -> Result<Option<PathBuf>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let Some(png) = png.filter(|_| config.format == SaveFormat::Png) else {
+        return save(image, lens, config).map(Some);
+    };
+    let path = next_path(lens, config)?;
+    fs::write(&path, png).with_context(|| format!("写入截图{}失败", path.display()))?;
+    Ok(Some(path))
+}
+
+/// 按模板生成文件名保存截图，不覆盖已有文件
+pub fn save(image: &Image, lens: &Lens, config: &SaveConfig) -> Result<PathBuf> {
+    let path = next_path(lens, config)?;
+    write(image, &path, config.format, config.quality)?;
+    Ok(path)
+}
+
+/// 弹出另存为对话框，默认位于截图目录、文件名按模板生成，格式按所选扩展名；取消时返回None
+pub fn save_as(image: &Image, lens: &Lens, config: &SaveConfig) -> Result<Option<PathBuf>> {
+    let default = next_path(lens, config)?;
+    let name = default
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // 配置的格式排在首位作为默认筛选
+    let formats = std::iter::once(config.format).chain(
+        SaveFormat::ALL
+            .into_iter()
+            .filter(|&format| format != config.format),
+    );
+    let dialog = formats.fold(
+        FileDialog::new()
+            .set_title("另存为")
+            .set_directory(&config.dir)
+            .set_file_name(name),
+        |dialog, format| {
+            dialog.add_filter(format.extension().to_uppercase(), &[format.extension()])
+        },
+    );
+    let Some(mut path) = dialog.save_file() else {
+        return Ok(None);
+    };
+    let format = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => SaveFormat::from_extension(extension).unwrap_or(config.format),
+        None => {
+            path.set_extension(config.format.extension());
+            config.format
+        }
+    };
+    write(image, &path, format, config.quality)?;
+    Ok(Some(path))
+}
+
+/// 按模板生成截图目录下尚不存在的文件路径，模板不含{seq}时同名文件追加序号
+fn next_path(lens: &Lens, config: &SaveConfig) -> Result<PathBuf> {
+    let tokens = parse(&config.template)?;
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let monitor = capture::monitor(lens).map_or(0, |index| index + 1);
+    fs::create_dir_all(&config.dir)
+        .with_context(|| format!("创建截图目录{}失败", config.dir.display()))?;
+
+    let has_seq = tokens.iter().any(|token| matches!(token, Token::Seq));
+    let mut attempt = 0;
+    loop {
+        let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+        let mut name = render(&tokens, &now, seq, monitor, lens)?;
+        if !has_seq && attempt > 0 {
+            name.push_str(&format!("_{attempt}"));
+        }
+        attempt += 1;
+        let path = config
+            .dir
+            .join(format!("{name}.{}", config.format.extension()));
+        if !path.exists() {
+            return Ok(path);
+        }
+    }
+}
+
+fn write(image: &Image, path: &Path, format: SaveFormat, quality: u8) -> Result<()> {
+    let data = image.encode(format.output(quality))?;
+    fs::write(path, data).with_context(|| format!("写入截图{}失败", path.display()))
+}
@@ -0,0 +1,131 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::{c_char, c_int},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use leptess::capi;
+
+/// tesseract C API的封装，LepTess不提供进度监视器，识别时无法取消
+pub struct Tesseract {
+    raw: *mut capi::TessBaseAPI,
+}
+
+impl Tesseract {
+    /// 加载模型，oem为引擎模式：0旧引擎、1 LSTM、2两者、3默认
+    pub fn new(tessdata_dir: &Path, lang: &str, oem: u32) -> Result<Self> {
+        let datapath = CString::new(tessdata_dir.to_string_lossy().as_bytes())?;
+        let language = CString::new(lang)?;
+        // # Safety
+        //
+        // raw由本结构独占，Drop时释放；字符串在调用期间有效
+        unsafe {
+            let raw = capi::TessBaseAPICreate();
+            if raw.is_null() {
+                return Err(anyhow!("创建tesseract失败"));
+            }
+            let tesseract = Self { raw };
+            if capi::TessBaseAPIInit2(raw, datapath.as_ptr(), language.as_ptr(), oem as _) != 0 {
+                return Err(anyhow!("加载OCR模型{lang}失败"));
+            }
+            Ok(tesseract)
+        }
+    }
+
+    /// 设置待识别的图片，图片未携带分辨率时使用dpi
+    pub fn set_image(&mut self, data: &[u8], dpi: i32) -> Result<()> {
+        unsafe {
+            let mut pix = capi::pixReadMem(data.as_ptr(), data.len() as _);
+            if pix.is_null() {
+                return Err(anyhow!("读取图片失败"));
+            }
+            capi::TessBaseAPISetImage2(self.raw, pix);
+            if capi::pixGetXRes(pix) <= 0 {
+                capi::TessBaseAPISetSourceResolution(self.raw, dpi);
+            }
+            // tesseract已复制图片
+            capi::pixDestroy(&mut pix);
+        }
+        Ok(())
+    }
+
+    /// 覆盖图片携带的分辨率
+    pub fn set_source_resolution(&mut self, dpi: i32) {
+        unsafe { capi::TessBaseAPISetSourceResolution(self.raw, dpi) };
+    }
+
+    /// 页面分割模式，0～13
+    pub fn set_page_seg_mode(&mut self, psm: u32) {
+        unsafe { capi::TessBaseAPISetPageSegMode(self.raw, psm as _) };
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: &str) -> Result<()> {
+        let c_name = CString::new(name)?;
+        let c_value = CString::new(value)?;
+        let ok =
+            unsafe { capi::TessBaseAPISetVariable(self.raw, c_name.as_ptr(), c_value.as_ptr()) };
+        if ok == 0 {
+            return Err(anyhow!("设置tesseract参数{name}={value}失败"));
+        }
+        Ok(())
+    }
+
+    /// 识别，cancel置为true时尽快中止，返回是否完成
+    pub fn recognize(&mut self, cancel: &AtomicBool) -> Result<bool> {
+        unsafe {
+            let monitor = capi::TessMonitorCreate();
+            if monitor.is_null() {
+                return Err(anyhow!("创建tesseract监视器失败"));
+            }
+            capi::TessMonitorSetCancelFunc(monitor, Some(cancel_func));
+            capi::TessMonitorSetCancelThis(monitor, cancel as *const AtomicBool as *mut c_void);
+            let result = capi::TessBaseAPIRecognize(self.raw, monitor);
+            capi::TessMonitorDelete(monitor);
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            if result != 0 {
+                return Err(anyhow!("识别失败"));
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn text(&mut self) -> Result<String> {
+        unsafe { take_text(capi::TessBaseAPIGetUTF8Text(self.raw)) }
+    }
+
+    pub fn tsv(&mut self) -> Result<String> {
+        unsafe { take_text(capi::TessBaseAPIGetTsvText(self.raw, 0)) }
+    }
+
+    pub fn hocr(&mut self) -> Result<String> {
+        unsafe { take_text(capi::TessBaseAPIGetHOCRText(self.raw, 0)) }
+    }
+
+    pub fn alto(&mut self) -> Result<String> {
+        unsafe { take_text(capi::TessBaseAPIGetAltoText(self.raw, 0)) }
+    }
+}
+
+impl Drop for Tesseract {
+    fn drop(&mut self) {
+        unsafe { capi::TessBaseAPIDelete(self.raw) };
+    }
+}
+
+unsafe extern "C" fn cancel_func(cancel_this: *mut c_void, _words: c_int) -> bool {
+    (*(cancel_this as *const AtomicBool)).load(Ordering::Relaxed)
+}
+
+/// 复制tesseract返回的字符串并释放
+unsafe fn take_text(text: *mut c_char) -> Result<String> {
+    if text.is_null() {
+        return Err(anyhow!("获取识别结果失败"));
+    }
+    let result = CStr::from_ptr(text).to_str().map(str::to_string);
+    capi::TessDeleteText(text);
+    Ok(result?)
+}
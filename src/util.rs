@@ -1,74 +1,117 @@
-use std::fs;
+use std::path::Path;
 
-use anyhow::{anyhow, Result};
-use clipboard_win::{formats, set_clipboard};
-use image::{open, ImageBuffer, Rgba};
+use anyhow::Result;
+use image::{open, ImageBuffer, Rgba, RgbaImage};
 use lazy_static::lazy_static;
-use leptess::LepTess;
 use log_error::LogError;
-use screenshots::{Image, Screen};
-use tracing::error;
+use screenshots::Image;
+use tracing::info;
 
-use crate::{capture::ScreenExt, image::ImageExt, lens::Lens};
+use crate::{
+    capture, clipboard, history,
+    lens::Lens,
+    ocr::{self, Cancel, Cancelled, Request},
+    output::{self, OcrFormat, OcrResult},
+};
+
+const ICON_FILE: &str = "middle-screenshot.ico";
+/// 执行目录下没有图标时使用的内置图标
+const DEFAULT_ICON: &[u8] = include_bytes!("../middle-screenshot.ico");
 
-const DEFAULT_DPI: i32 = 72;
 lazy_static! {
-    static ref LANG: String = {
-        let train_files: Vec<String> = fs::read_dir(".")
-            .log_error("读取tesseract预训练模型失败")
-            .unwrap()
-            .filter_map(|f| {
-                if let Ok(f) = f {
-                    let file_name = f.file_name();
-                    let file_name = file_name.to_string_lossy();
-                    if file_name.ends_with(".traineddata") {
-                        let file_name = file_name.trim_end_matches(".traineddata").to_string();
-                        return Some(file_name);
-                    }
-                    None
-                } else {
-                    None
-                }
-            })
-            .collect();
-        if train_files.is_empty() {
-            let err_msg = "请下载拷贝tesseract预训练模型至运行目录";
-            error!("{err_msg}");
-            panic!("{err_msg}");
-        }
-        train_files.join("+")
-    };
-    static ref ICON: ImageBuffer<Rgba<u8>, Vec<u8>> = open("middle-screenshot.ico")
+    static ref ICON: ImageBuffer<Rgba<u8>, Vec<u8>> = load_icon()
+        .or_else(|_| image::load_from_memory(DEFAULT_ICON).map(|icon| icon.into_rgba8()))
         .log_error("读取ICON失败")
-        .unwrap()
-        .into_rgba8();
+        .unwrap_or_else(|| ImageBuffer::from_pixel(32, 32, Rgba([0x33, 0x99, 0xff, 0xff])));
+}
+
+/// 读取执行目录下的图标，可替换为自定义图标
+pub fn load_icon() -> image::ImageResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    Ok(open(ICON_FILE)?.into_rgba8())
 }
 
-/// 调用tesseract进行ocr
-pub fn ocr(tiff: &[u8]) -> Result<String> {
-    let mut tesseract = LepTess::new(None, &LANG)?;
-    tesseract.set_image_from_mem(tiff)?;
-    tesseract.set_fallback_source_resolution(DEFAULT_DPI);
-    tesseract.set_variable(leptess::Variable::PreserveInterwordSpaces, "1")?;
-    Ok(tesseract.get_utf8_text()?)
+/// 调用tesseract进行ocr，lens为截图在屏幕上的位置；识别后才取消的结果同样丢弃。
+/// 结果需在事件循环中确认识别未被取消或取代后再用deliver_ocr输出
+pub fn ocr(
+    image: RgbaImage,
+    lens: Lens,
+    history: Option<u64>,
+    request: &Request,
+    format: OcrFormat,
+    cancel: &Cancel,
+) -> Result<OcrResult> {
+    let recognition = ocr::recognize(image, request, format, cancel)?;
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
+    }
+    Ok(OcrResult {
+        recognition,
+        format,
+        lens,
+        history,
+    })
+}
+
+/// 识别结果的文字记入历史，按格式输出到剪切板或文件
+pub fn deliver_ocr(result: &OcrResult) -> Result<()> {
+    if let Some(id) = result.history {
+        history::set_text(id, &result.recognition.text).log_error("记录OCR结果失败");
+    }
+    output::deliver(
+        &result.recognition,
+        result.format,
+        &result.lens,
+        result.history,
+    )
+}
+
+/// 记录OCR失败，取消不算失败
+pub fn log_ocr<T>(result: Result<T>) -> Option<T> {
+    match result {
+        Err(e) if e.is::<Cancelled>() => {
+            info!("{e}");
+            None
+        }
+        result => result.log_error("OCR失败"),
+    }
 }
 
 /// 截图
 pub fn screenshot(lens: &Lens) -> Result<Image> {
-    let screen = Screen::from_point(lens.x, lens.y)?;
-    let image = screen.capture_lens(lens)?;
-    Ok(image)
+    capture::capture(lens)
 }
 
 /// 复制图片到剪切板
 pub fn copy_image(image: &Image) -> Result<()> {
-    let bmp = image.to_bmp()?;
-    set_clipboard(formats::Bitmap, bmp).map_err(|e| anyhow!(e))
+    clipboard::set_image(image)
 }
 
 /// 复制文字到剪切板
 pub fn copy_text(text: String) -> Result<()> {
-    set_clipboard(formats::Unicode, text).map_err(|e| anyhow!(e))
+    clipboard::set_text(&text)
+}
+
+/// 在文件管理器中显示文件
+pub fn reveal(path: &Path) {
+    #[cfg(windows)]
+    let result = std::process::Command::new("explorer")
+        .arg(format!("/select,{}", path.display()))
+        .spawn();
+    #[cfg(not(windows))]
+    let result = std::process::Command::new("xdg-open")
+        .arg(path.parent().unwrap_or(path))
+        .spawn();
+    result.map(|_| ()).log_error("打开文件管理器失败");
+}
+
+/// 系统通知
+pub fn notify(body: &str) {
+    notify_rust::Notification::new()
+        .summary("中键截屏")
+        .body(body)
+        .show()
+        .map(|_| ())
+        .log_error("发送通知失败");
 }
 
 pub fn get_tray_icon() -> std::result::Result<tray_icon::Icon, tray_icon::BadIcon> {
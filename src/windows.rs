@@ -1,66 +1,740 @@
-use crate::event::Event;
-use crate::render::State;
+use crate::binding::{Modifiers, Trigger};
+use crate::config::{self, Config, SaveConfig, WindowConfig};
+use crate::diagnose;
+use crate::event::{Event, PinAction};
+use crate::history;
+use crate::image::ImageExt;
+use crate::lens::Lens;
+use crate::ocr::{Cancel, Request};
+use crate::output::OcrResult;
+use crate::render::{Action, Gallery, Overlay, Renderer, State, Target};
+use crate::save;
 use crate::util;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
 use log_error::LogError;
 use screenshots::Image;
 use std::collections::HashMap;
+use std::thread;
+use tracing::{error, info};
+use tray_icon::menu::Menu;
 use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
 use winit::event_loop::{EventLoopProxy, EventLoopWindowTarget};
-use winit::window::{WindowBuilder, WindowId, WindowLevel};
+use winit::window::{Window, WindowBuilder, WindowId, WindowLevel};
 
 pub struct Windows {
+    /// 共享渲染方式，首个窗口创建时初始化
+    renderer: Option<Renderer>,
+    /// GPU与CPU绘制都不可用，不再创建窗口
+    headless: bool,
+    /// 最近一次截图，供托盘菜单OCR，无窗口时也能识别
+    last: Option<(Image, Lens, Option<u64>)>,
+    /// 进行中的上次截图或历史记录识别及其编号，新的识别开始时取消
+    ocr_job: Option<(u64, Cancel)>,
+    /// 已发起的上次截图或历史记录识别数，用作识别编号
+    ocr_jobs: u64,
     windows: HashMap<WindowId, State>,
+    overlay: Option<Overlay>,
+    gallery: Option<Gallery>,
+    /// 正在拖动的贴图及按下时光标在窗口内的位置
+    drag: Option<(WindowId, PhysicalPosition<f64>)>,
+    /// 光标在所在贴图窗口内的位置
+    cursor: PhysicalPosition<f64>,
+    modifiers: ModifiersState,
+    /// 正在拖动选择文字的贴图
+    selecting: Option<WindowId>,
+    /// 最近操作的贴图，未指定目标的贴图操作作用于该贴图
+    active: Option<WindowId>,
+    /// 弹出右键菜单的贴图，菜单操作作用于该贴图
+    menu_window: Option<WindowId>,
     event_loop: EventLoopProxy<Event>,
 }
 
 impl Windows {
     pub fn new(event_loop: EventLoopProxy<Event>) -> Self {
         Self {
+            renderer: None,
+            headless: false,
+            last: None,
+            ocr_job: None,
+            ocr_jobs: 0,
             windows: HashMap::new(),
+            overlay: None,
+            gallery: None,
+            drag: None,
+            cursor: PhysicalPosition::new(0.0, 0.0),
+            modifiers: ModifiersState::empty(),
+            selecting: None,
+            active: None,
+            menu_window: None,
             event_loop,
         }
     }
 
+    /// 创建贴图窗口并返回其id，无窗口模式时只记录截图
     pub fn create(
         &mut self,
         event_loop: &EventLoopWindowTarget<Event>,
         image: Image,
         size: PhysicalSize<u32>,
         position: PhysicalPosition<i32>,
-    ) -> Result<()> {
+        config: &WindowConfig,
+        history: Option<u64>,
+    ) -> Result<Option<WindowId>> {
+        let lens = Lens {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        };
+        self.last = Some((
+            Image::new(image.width(), image.height(), image.rgba().to_vec()),
+            lens,
+            history,
+        ));
+        if self.headless {
+            return Ok(None);
+        }
+        let window_level = if config.always_on_top {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        };
         let window = WindowBuilder::new()
             .with_title("中键截屏（OCR）")
             .with_window_icon(util::get_window_icon().ok())
             .with_visible(false)
             .with_inner_size(size)
             .with_position(position)
-            .with_window_level(WindowLevel::AlwaysOnTop)
-            .with_decorations(false)
-            .with_resizable(false)
-            .with_transparent(true)
+            .with_window_level(window_level)
+            .with_decorations(config.decorations)
+            .with_resizable(config.resizable)
+            .with_transparent(config.transparent)
             .build(event_loop)?;
-        let mut state = pollster::block_on(async { State::new(window, image, size).await });
-        state.render()?;
+        let Some((renderer, target)) = self.prepare(&window)? else {
+            return Ok(None);
+        };
+        let mut state = State::new(renderer, window, target, image, size)?;
+        state.set_history(history);
+        state.set_source(lens);
+        state.render(renderer)?;
         state.visible();
-        self.windows.insert(state.get_id(), state);
+        let window_id = state.get_id();
+        self.windows.insert(window_id, state);
+        Ok(Some(window_id))
+    }
+
+    /// 显示选区遮罩，首次使用时按虚拟桌面范围创建
+    pub fn select(&mut self, event_loop: &EventLoopWindowTarget<Event>, lens: &Lens) -> Result<()> {
+        if self.headless || matches!(self.renderer, Some(Renderer::Soft)) {
+            // CPU绘制无法实现半透明遮罩，直接截图
+            return Ok(());
+        }
+        if self.overlay.is_none() {
+            let (position, size) = desktop_bounds(event_loop);
+            let builder = WindowBuilder::new()
+                .with_title("中键截屏（选区）")
+                .with_visible(false)
+                .with_inner_size(size)
+                .with_position(position)
+                .with_window_level(WindowLevel::AlwaysOnTop)
+                .with_decorations(false)
+                .with_resizable(false)
+                .with_transparent(true);
+            #[cfg(windows)]
+            let builder = {
+                use winit::platform::windows::WindowBuilderExtWindows;
+                builder.with_skip_taskbar(true)
+            };
+            let window = builder.build(event_loop)?;
+            window.set_cursor_hittest(false)?;
+            match self.prepare(&window)? {
+                Some((Renderer::Gpu(context), Target::Gpu(surface))) => {
+                    self.overlay = Some(Overlay::new(context, window, surface, position, size)?);
+                }
+                _ => return Ok(()),
+            }
+        }
+
+        let context = self.renderer.as_ref().and_then(Renderer::context);
+        if let (Some(context), Some(overlay)) = (context, self.overlay.as_mut()) {
+            overlay.render(context, lens)?;
+            overlay.visible(true);
+        }
         Ok(())
     }
 
-    pub fn ocr(&mut self, window_id: &WindowId) -> Result<()> {
+    /// 隐藏选区遮罩
+    pub fn unselect(&mut self) {
+        if let Some(overlay) = self.overlay.as_ref() {
+            overlay.visible(false);
+        }
+    }
+
+    pub fn ocr(&mut self, window_id: &WindowId, request: Request) -> Result<()> {
+        diagnose::check_ocr()?;
         let state = self.windows.get_mut(window_id);
         if let Some(state) = state {
             let event_loop = self.event_loop.clone();
-            state.ocr(event_loop)?;
+            state.ocr(event_loop, request)?;
+        }
+        Ok(())
+    }
+
+    /// 处理贴图窗口的输入：左键拖动移动、方向键微调、滚轮缩放、Ctrl+滚轮调节不透明度，其它按键按快捷键配置转为贴图操作
+    pub fn pin_input(
+        &mut self,
+        event_loop: &EventLoopWindowTarget<Event>,
+        window_id: &WindowId,
+        event: &WindowEvent,
+        config: &Config,
+    ) -> Result<()> {
+        match *event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                return Ok(());
+            }
+            WindowEvent::Resized(size) => {
+                if let (Some(renderer), Some(state)) =
+                    (self.renderer.as_ref(), self.windows.get_mut(window_id))
+                {
+                    state.resize(renderer, size);
+                }
+                return Ok(());
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y.signum() as i32,
+                    MouseScrollDelta::PixelDelta(position) => position.y.signum() as i32,
+                };
+                if steps == 0 {
+                    return Ok(());
+                }
+                let (Some(renderer), Some(state)) =
+                    (self.renderer.as_ref(), self.windows.get_mut(window_id))
+                else {
+                    return Ok(());
+                };
+                let event_loop = self.event_loop.clone();
+                // Ctrl+滚轮调节不透明度，否则以光标为中心缩放
+                return if self.modifiers.ctrl() {
+                    state.opacity_by(renderer, steps, event_loop)
+                } else {
+                    state.zoom_by(renderer, steps, self.cursor, event_loop)
+                };
+            }
+            _ => {}
+        }
+        let Some(state) = self.windows.get_mut(window_id) else {
+            return Ok(());
+        };
+        match *event {
+            WindowEvent::Focused(true) => {
+                self.active = Some(*window_id);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.active = Some(*window_id);
+                let had_selection = state.selected_text().is_some();
+                // 在识别到的词上按下时选择文字，否则拖动窗口
+                if state.select_from(self.cursor) {
+                    self.selecting = Some(*window_id);
+                } else {
+                    self.drag = Some((*window_id, self.cursor));
+                    state.checkpoint()?;
+                }
+                if had_selection || self.selecting.is_some() {
+                    self.event_loop.send_event(Event::Redraw(*window_id))?;
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            }
+            | WindowEvent::Focused(false) => {
+                self.drag = None;
+                self.selecting = None;
+            }
+            // 按下左键时窗口捕获光标，快速拖动移出窗口后仍能收到移动与松开事件，不在此结束拖动
+            WindowEvent::CursorLeft { .. } if state.hover(None) => {
+                self.event_loop.send_event(Event::Redraw(*window_id))?;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = position;
+                if state.hover(Some(position)) {
+                    self.event_loop.send_event(Event::Redraw(*window_id))?;
+                }
+                if self.selecting == Some(*window_id) {
+                    state.select_to(position);
+                    self.event_loop.send_event(Event::Redraw(*window_id))?;
+                    return Ok(());
+                }
+                let Some((_, grab)) = self.drag.filter(|(id, _)| id == window_id) else {
+                    return Ok(());
+                };
+                let origin = state.window().outer_position()?;
+                // 光标位置相对窗口，窗口跟随移动后按住点保持不变
+                let x = origin.x + (position.x - grab.x).round() as i32;
+                let y = origin.y + (position.y - grab.y).round() as i32;
+                let position =
+                    self.snap(event_loop, window_id, (x, y), config.window.snap_distance)?;
+                if let Some(state) = self.windows.get(window_id) {
+                    state.window().set_outer_position(position);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                self.active = Some(*window_id);
+                let action = Trigger::from_virtual_key(key).and_then(|trigger| {
+                    config
+                        .keymap
+                        .action(&trigger, &Modifiers::from(self.modifiers))
+                });
+                if let Some(action) = action {
+                    // 识别进行中时关闭键只取消识别，保留贴图
+                    let action = match action {
+                        PinAction::Close if state.ocring() => PinAction::CancelOcr,
+                        action => action,
+                    };
+                    self.event_loop
+                        .send_event(Event::Pin(Some(*window_id), action))?;
+                    return Ok(());
+                }
+                let step = if self.modifiers.shift() {
+                    config.window.nudge_step as i32 * 10
+                } else {
+                    config.window.nudge_step as i32
+                };
+                let (dx, dy) = match key {
+                    VirtualKeyCode::Left => (-step, 0),
+                    VirtualKeyCode::Right => (step, 0),
+                    VirtualKeyCode::Up => (0, -step),
+                    VirtualKeyCode::Down => (0, step),
+                    _ => return Ok(()),
+                };
+                state.checkpoint()?;
+                let window = state.window();
+                let origin = window.outer_position()?;
+                window.set_outer_position(PhysicalPosition::new(origin.x + dx, origin.y + dy));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// 在光标处弹出贴图右键菜单，不支持弹出菜单的平台直接OCR
+    pub fn show_menu(&mut self, window_id: &WindowId, menu: &Menu) -> Result<()> {
+        #[cfg(windows)]
+        {
+            use tray_icon::menu::ContextMenu;
+            use winit::platform::windows::WindowExtWindows;
+
+            self.drag = None;
+            if let Some(state) = self.windows.get(window_id) {
+                self.active = Some(*window_id);
+                self.menu_window = Some(*window_id);
+                menu.show_context_menu_for_hwnd(
+                    state.window().hwnd(),
+                    self.cursor.x,
+                    self.cursor.y,
+                );
+            }
+            Ok(())
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = menu;
+            self.ocr(window_id, Request::default())
+        }
+    }
+
+    /// 对window_id执行贴图操作，为None时作用于弹出菜单的贴图，没有时作用于最近操作的贴图
+    pub fn pin_action(
+        &mut self,
+        window_id: Option<WindowId>,
+        action: PinAction,
+        save_config: &SaveConfig,
+    ) -> Result<()> {
+        let target = window_id
+            .or_else(|| self.menu_window.take())
+            .or(self.active);
+        if action == PinAction::CloseAll {
+            let ids: Vec<WindowId> = self.windows.keys().copied().collect();
+            for id in ids {
+                self.destroy(&id);
+            }
+            return Ok(());
+        }
+        let Some(window_id) = target.filter(|id| self.windows.contains_key(id)) else {
+            return Ok(());
+        };
+        let event_loop = self.event_loop.clone();
+        match action {
+            PinAction::Ocr => self.ocr(&window_id, Request::default())?,
+            PinAction::OcrWith(profile) => self.ocr(&window_id, Request::profile(profile))?,
+            PinAction::OcrPreset(preset) => self.ocr(&window_id, Request::preset(preset))?,
+            PinAction::Close => self.destroy(&window_id),
+            PinAction::CloseAll => {}
+            action => {
+                let (Some(renderer), Some(state)) =
+                    (self.renderer.as_ref(), self.windows.get_mut(&window_id))
+                else {
+                    return Ok(());
+                };
+                match action {
+                    PinAction::Copy => match state.selected_text() {
+                        Some(text) => util::copy_text(text)?,
+                        None => util::copy_image(state.image())?,
+                    },
+                    PinAction::CopyImage => util::copy_image(state.image())?,
+                    PinAction::CopyText => {
+                        if let Some(text) = state.text() {
+                            util::copy_text(text)?;
+                        }
+                    }
+                    PinAction::Save => {
+                        let path = save::save(state.image(), &state.lens()?, save_config)?;
+                        info!("截图已保存至{}", path.display());
+                        util::reveal(&path);
+                    }
+                    PinAction::SaveAs => {
+                        if let Some(path) =
+                            save::save_as(state.image(), &state.lens()?, save_config)?
+                        {
+                            info!("截图已保存至{}", path.display());
+                        }
+                    }
+                    PinAction::Zoom(zoom) => {
+                        let center = state.center();
+                        state.set_zoom(renderer, zoom, center, event_loop)?;
+                    }
+                    PinAction::ZoomIn | PinAction::ZoomOut => {
+                        let steps = if action == PinAction::ZoomIn { 1 } else { -1 };
+                        let center = state.center();
+                        state.zoom_by(renderer, steps, center, event_loop)?;
+                    }
+                    PinAction::Undo => state.undo(renderer, event_loop)?,
+                    PinAction::Redo => state.redo(renderer, event_loop)?,
+                    PinAction::Opacity(opacity) => {
+                        state.set_opacity(renderer, opacity, event_loop)?;
+                    }
+                    PinAction::ClickThrough => {
+                        self.drag = None;
+                        state.set_click_through(true)?;
+                    }
+                    PinAction::CancelOcr if state.cancel_ocr() => {
+                        event_loop.send_event(Event::Redraw(window_id))?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 贴图识别完成，忽略已被取消或取代的识别；输出失败时保留贴图
+    pub fn ocr_done(&mut self, window_id: &WindowId, job: u64, result: OcrResult) -> Result<()> {
+        let Some(show_words) = self
+            .windows
+            .get_mut(window_id)
+            .and_then(|state| state.finish_ocr(job))
+        else {
+            info!("忽略过期的识别结果");
+            return Ok(());
+        };
+        let delivered = util::log_ocr(self.deliver(&result)).is_some();
+        if delivered && !show_words {
+            self.destroy(window_id);
+            return Ok(());
+        }
+        if let (Some(renderer), Some(state)) = (
+            self.renderer.as_ref(),
+            self.windows.get_mut(window_id).filter(|_| delivered),
+        ) {
+            state.set_words(renderer, result.recognition.words)?;
+        }
+        self.event_loop.send_event(Event::Redraw(*window_id))?;
+        Ok(())
+    }
+
+    /// 上次截图或历史记录识别完成，忽略已被新的识别取代的结果
+    pub fn ocr_last_done(&mut self, job: u64, result: OcrResult) -> Result<()> {
+        if self.ocr_job.as_ref().map(|(id, _)| *id) != Some(job) {
+            info!("忽略过期的识别结果");
+            return Ok(());
+        }
+        self.ocr_job = None;
+        self.deliver(&result)
+    }
+
+    /// 输出识别结果，有历史记录时刷新历史记录窗口
+    fn deliver(&self, result: &OcrResult) -> Result<()> {
+        let delivered = util::deliver_ocr(result);
+        if result.history.is_some() {
+            self.event_loop.send_event(Event::HistoryChanged)?;
+        }
+        delivered
+    }
+
+    /// 识别失败，停止动画
+    pub fn ocr_failed(&mut self, window_id: &WindowId, job: u64) -> Result<()> {
+        if let Some(state) = self.windows.get_mut(window_id) {
+            if state.finish_ocr(job).is_some() {
+                self.event_loop.send_event(Event::Redraw(*window_id))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 取消所有贴图的鼠标穿透
+    pub fn clear_click_through(&self) -> Result<()> {
+        for state in self.windows.values() {
+            state.set_click_through(false)?;
         }
         Ok(())
     }
 
+    /// 窗口边缘靠近屏幕边缘或其它贴图边缘时吸附
+    fn snap(
+        &self,
+        event_loop: &EventLoopWindowTarget<Event>,
+        window_id: &WindowId,
+        (x, y): (i32, i32),
+        distance: u32,
+    ) -> Result<PhysicalPosition<i32>> {
+        let Some(state) = self.windows.get(window_id) else {
+            return Ok(PhysicalPosition::new(x, y));
+        };
+        if distance == 0 {
+            return Ok(PhysicalPosition::new(x, y));
+        }
+        let size = state.window().outer_size();
+        let moving = Lens {
+            x,
+            y,
+            width: size.width,
+            height: size.height,
+        };
+
+        let mut targets: Vec<Lens> = event_loop
+            .available_monitors()
+            .map(|monitor| {
+                let position = monitor.position();
+                let size = monitor.size();
+                Lens {
+                    x: position.x,
+                    y: position.y,
+                    width: size.width,
+                    height: size.height,
+                }
+            })
+            .collect();
+        for (id, other) in self.windows.iter() {
+            if id == window_id {
+                continue;
+            }
+            let position = other.window().outer_position()?;
+            let size = other.window().outer_size();
+            targets.push(Lens {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+            });
+        }
+
+        let distance = distance as i32;
+        // 每个方向取距离最近的一条边对齐
+        let nearest = |edges: [i32; 2], lines: Vec<i32>| {
+            lines
+                .iter()
+                .flat_map(|line| edges.map(|edge| line - edge))
+                .filter(|delta| delta.abs() <= distance)
+                .min_by_key(|delta| delta.abs())
+                .unwrap_or(0)
+        };
+        let dx = nearest(
+            [moving.x, moving.right()],
+            targets.iter().flat_map(|t| [t.x, t.right()]).collect(),
+        );
+        let dy = nearest(
+            [moving.y, moving.bottom()],
+            targets.iter().flat_map(|t| [t.y, t.bottom()]).collect(),
+        );
+        Ok(PhysicalPosition::new(x + dx, y + dy))
+    }
+
+    /// 打开历史记录窗口，已打开时刷新并置前
+    pub fn show_history(&mut self, event_loop: &EventLoopWindowTarget<Event>) -> Result<()> {
+        if let (Some(renderer), Some(gallery)) = (self.renderer.as_ref(), self.gallery.as_mut()) {
+            gallery.refresh(renderer)?;
+            gallery.focus();
+            return Ok(());
+        }
+        if self.headless {
+            return Err(anyhow!("窗口不可用，无法打开历史记录"));
+        }
+        let window = WindowBuilder::new()
+            .with_title("历史记录")
+            .with_window_icon(util::get_window_icon().ok())
+            .with_visible(false)
+            .with_inner_size(Gallery::size())
+            .with_resizable(false)
+            .build(event_loop)?;
+        let Some((renderer, target)) = self.prepare(&window)? else {
+            return Ok(());
+        };
+        let gallery = Gallery::new(renderer, window, target)?;
+        gallery.focus();
+        self.gallery = Some(gallery);
+        Ok(())
+    }
+
+    pub fn is_gallery(&self, window_id: &WindowId) -> bool {
+        matches!(&self.gallery, Some(gallery) if gallery.get_id() == *window_id)
+    }
+
+    /// 历史记录变化后刷新历史窗口
+    pub fn history_changed(&mut self) -> Result<()> {
+        if let (Some(renderer), Some(gallery)) = (self.renderer.as_ref(), self.gallery.as_mut()) {
+            gallery.refresh(renderer)?;
+        }
+        Ok(())
+    }
+
+    /// 处理历史窗口的输入
+    pub fn gallery_input(
+        &mut self,
+        event_loop: &EventLoopWindowTarget<Event>,
+        event: &WindowEvent,
+        config: &WindowConfig,
+    ) -> Result<()> {
+        let (Some(renderer), Some(gallery)) = (self.renderer.as_ref(), self.gallery.as_mut())
+        else {
+            return Ok(());
+        };
+        match gallery.input(renderer, event)? {
+            Some(Action::Copy(id)) => util::copy_image(&history::image(id)?)?,
+            Some(Action::Pin(id)) => {
+                if let Some(entry) = history::get(id) {
+                    let Lens {
+                        x,
+                        y,
+                        width,
+                        height,
+                    } = entry.lens;
+                    self.create(
+                        event_loop,
+                        history::image(id)?,
+                        PhysicalSize { width, height },
+                        PhysicalPosition { x, y },
+                        config,
+                        Some(id),
+                    )?;
+                }
+            }
+            Some(Action::Ocr(id)) => {
+                if let Some(entry) = history::get(id) {
+                    let image = history::image(id)?.to_rgba_image()?;
+                    self.ocr_image(image, entry.lens, Some(id), Request::default())?;
+                }
+            }
+            Some(Action::Delete(id)) => {
+                history::remove(id)?;
+                gallery.refresh(renderer)?;
+            }
+            Some(Action::Close) => self.gallery = None,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// 后台OCR，结果写入历史记录并按配置输出；上一次后台识别未完成时先取消
+    fn ocr_image(
+        &mut self,
+        image: RgbaImage,
+        lens: Lens,
+        history: Option<u64>,
+        request: Request,
+    ) -> Result<()> {
+        diagnose::check_ocr()?;
+        if let Some((_, cancel)) = self.ocr_job.take() {
+            cancel.cancel();
+        }
+        let format = config::current().ocr.format;
+        let event_loop = self.event_loop.clone();
+        self.ocr_jobs += 1;
+        let job = self.ocr_jobs;
+        let cancel = Cancel::default();
+        self.ocr_job = Some((job, cancel.clone()));
+        thread::spawn(move || {
+            let recognition = util::ocr(image, lens, history, &request, format, &cancel);
+            if let Some(result) = util::log_ocr(recognition) {
+                event_loop
+                    .send_event(Event::OcrLastDone(job, result))
+                    .log_error("发送识别结果失败");
+            }
+        });
+        Ok(())
+    }
+
+    /// 对最近一次截图OCR
+    pub fn ocr_last(&mut self, request: Request) -> Result<()> {
+        let Some((image, lens, history)) = self.last.as_ref() else {
+            return Err(anyhow!("没有可识别的截图"));
+        };
+        let (image, lens, history) = (image.to_rgba_image()?, *lens, *history);
+        self.ocr_image(image, lens, history, request)
+    }
+
+    /// 为新窗口准备显示目标，首个窗口创建时选定渲染方式，都不可用时转为无窗口模式
+    fn prepare(&mut self, window: &Window) -> Result<Option<(&Renderer, Target)>> {
+        if self.renderer.is_none() {
+            match Renderer::new(window) {
+                Ok((renderer, target)) => {
+                    return Ok(Some((self.renderer.insert(renderer), target)));
+                }
+                Err(e) => {
+                    error!("{e:#}，贴图窗口不可用");
+                    util::notify("无法创建贴图窗口，截图仍会复制到剪切板，可通过托盘菜单“识别上次截图”进行OCR");
+                    self.headless = true;
+                    return Ok(None);
+                }
+            }
+        }
+        let renderer = self
+            .renderer
+            .as_ref()
+            .ok_or_else(|| anyhow!("渲染方式未初始化"))?;
+        Ok(Some((renderer, renderer.target(window)?)))
+    }
+
     pub fn redraw(&mut self, window_id: WindowId) -> Result<()> {
+        let Some(renderer) = self.renderer.as_ref() else {
+            return Ok(());
+        };
+        if let Some(gallery) = self.gallery.as_mut().filter(|g| g.get_id() == window_id) {
+            return gallery.render(renderer);
+        }
         let state = self.windows.get_mut(&window_id);
         if let Some(state) = state {
-            state.render()?;
-            if state.ocring() {
+            state.render(renderer)?;
+            if state.animating() {
                 self.event_loop
                     .send_event(Event::Redraw(window_id))
                     .log_error("发起重绘失败");
@@ -70,6 +744,43 @@ impl Windows {
     }
 
     pub fn destroy(&mut self, window_id: &WindowId) {
+        if matches!(self.drag, Some((id, _)) if id == *window_id) {
+            self.drag = None;
+        }
+        if self.selecting == Some(*window_id) {
+            self.selecting = None;
+        }
+        if self.menu_window == Some(*window_id) {
+            self.menu_window = None;
+        }
+        if self.is_gallery(window_id) {
+            self.gallery = None;
+        }
         self.windows.remove(window_id);
     }
 }
+
+/// 所有显示器组成的虚拟桌面范围（物理像素）
+fn desktop_bounds(
+    event_loop: &EventLoopWindowTarget<Event>,
+) -> (PhysicalPosition<i32>, PhysicalSize<u32>) {
+    let mut left = i32::MAX;
+    let mut top = i32::MAX;
+    let mut right = i32::MIN;
+    let mut bottom = i32::MIN;
+    for monitor in event_loop.available_monitors() {
+        let position = monitor.position();
+        let size = monitor.size();
+        left = left.min(position.x);
+        top = top.min(position.y);
+        right = right.max(position.x + size.width as i32);
+        bottom = bottom.max(position.y + size.height as i32);
+    }
+    if left > right || top > bottom {
+        return (PhysicalPosition::new(0, 0), PhysicalSize::new(1, 1));
+    }
+    (
+        PhysicalPosition::new(left, top),
+        PhysicalSize::new((right - left) as u32, (bottom - top) as u32),
+    )
+}